@@ -0,0 +1,51 @@
+varying vec2 v_uv;
+varying vec3 v_normal;
+varying float v_part;
+
+#ifdef VERTEX_SHADER
+attribute vec3 a_pos;
+attribute vec2 a_uv;
+attribute vec3 a_normal;
+attribute float a_part;
+
+uniform mat4 u_view_matrix;
+uniform mat4 u_projection_matrix;
+
+void main() {
+  v_uv = a_uv;
+  v_normal = a_normal;
+  v_part = a_part;
+  gl_Position = u_projection_matrix * u_view_matrix * vec4(a_pos, 1.0);
+}
+#endif
+
+#ifdef FRAGMENT_SHADER
+uniform sampler2D u_texture;
+
+// 0 - texture, 1 - normal direction, 2 - uv checkerboard, 3 - front / back / side
+uniform int u_mode;
+uniform float u_checker_size;
+
+void main() {
+  vec3 color;
+  if (u_mode == 1) {
+    // Missing normals stand out in magenta
+    color = length(v_normal) > 0.0 ? normalize(v_normal) * 0.5 + 0.5 : vec3(1.0, 0.0, 1.0);
+  } else if (u_mode == 2) {
+    vec2 cell = floor(v_uv * u_checker_size);
+    float checker = mod(cell.x + cell.y, 2.0);
+    color = mix(vec3(0.2), vec3(0.9), checker);
+  } else if (u_mode == 3) {
+    if (v_part < 0.5) {
+      color = vec3(0.9, 0.3, 0.3);
+    } else if (v_part < 1.5) {
+      color = vec3(0.3, 0.4, 0.9);
+    } else {
+      color = vec3(0.3, 0.9, 0.4);
+    }
+  } else {
+    color = texture2D(u_texture, v_uv).rgb;
+  }
+  gl_FragColor = vec4(color, 1.0);
+}
+#endif
@@ -0,0 +1,23 @@
+varying vec2 v_uv;
+
+#ifdef VERTEX_SHADER
+attribute vec2 a_pos;
+attribute vec2 a_uv;
+
+uniform mat3 u_matrix;
+
+void main() {
+  v_uv = a_uv;
+  vec3 pos = u_matrix * vec3(a_pos, 1.0);
+  gl_Position = vec4(pos.xy, 0.0, 1.0);
+}
+#endif
+
+#ifdef FRAGMENT_SHADER
+uniform sampler2D u_texture;
+uniform vec4 u_color;
+
+void main() {
+  gl_FragColor = texture2D(u_texture, v_uv) * u_color;
+}
+#endif
@@ -1,10 +1,14 @@
 varying vec2 v_uv;
 varying vec4 v_color;
+varying vec3 v_normal;
+varying vec3 v_view_normal;
+varying vec3 v_view_pos;
 
 #ifdef VERTEX_SHADER
 attribute vec3 a_pos;
 attribute vec2 a_uv;
 attribute vec4 a_color;
+attribute vec3 a_normal;
 
 uniform mat4 u_view_matrix;
 uniform mat4 u_projection_matrix;
@@ -12,14 +16,37 @@ uniform mat4 u_projection_matrix;
 void main() {
   v_uv = a_uv;
   v_color = a_color;
-  gl_Position = u_projection_matrix * u_view_matrix * vec4(a_pos, 1.0);
+  v_normal = a_normal;
+  // GLSL ES 1.00 has no matrix from matrix constructor
+  v_view_normal = mat3(u_view_matrix[0].xyz, u_view_matrix[1].xyz, u_view_matrix[2].xyz) * a_normal;
+  vec4 view_pos = u_view_matrix * vec4(a_pos, 1.0);
+  v_view_pos = view_pos.xyz;
+  gl_Position = u_projection_matrix * view_pos;
 }
 #endif
 
 #ifdef FRAGMENT_SHADER
 uniform sampler2D u_texture;
 
+// 1.0 to shade with the lights below, 0.0 for the raw texture color
+uniform float u_lighting;
+// Direction towards the light in world space
+uniform vec3 u_light_direction;
+uniform vec4 u_light_color;
+uniform float u_ambient;
+uniform float u_rim;
+
 void main() {
-  gl_FragColor = texture2D(u_texture, v_uv);
+  vec4 color = texture2D(u_texture, v_uv);
+  // Normals can be missing (zero) on degenerate side walls
+  if (u_lighting > 0.5 && length(v_normal) > 0.0) {
+    vec3 normal = normalize(v_normal);
+    float diffuse = max(dot(normal, normalize(u_light_direction)), 0.0);
+    float facing = max(dot(normalize(v_view_normal), normalize(-v_view_pos)), 0.0);
+    float rim = u_rim * pow(1.0 - facing, 3.0);
+    color.rgb = color.rgb * (u_ambient + diffuse * u_light_color.rgb) + rim * u_light_color.rgb;
+  }
+  // Drawn opaque like with the default export, texture alpha only matters to other alpha modes
+  gl_FragColor = vec4(color.rgb, 1.0);
 }
 #endif
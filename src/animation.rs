@@ -0,0 +1,227 @@
+//! Encoding of rendered frames into still and animated images, tagged with the [Provenance]
+
+use super::*;
+
+/// Keyword of the PNG text chunk holding the [Provenance]
+pub const PROVENANCE_KEYWORD: &str = "sprite_shape";
+
+/// [Provenance] as JSON with non-ASCII characters escaped, PNG text chunks are Latin-1
+pub fn provenance_text(provenance: &Provenance) -> String {
+    let json = serde_json::to_string(provenance).expect("Serialization error");
+    let mut text = String::with_capacity(json.len());
+    for c in json.chars() {
+        if c.is_ascii() {
+            text.push(c);
+        } else {
+            // Only strings contain non-ASCII characters, so an escape is always valid
+            for unit in c.encode_utf16(&mut [0; 2]) {
+                text.push_str(&format!("\\u{unit:04x}"));
+            }
+        }
+    }
+    text
+}
+
+/// Still PNG with the [Provenance] in a text chunk
+pub fn encode_png(
+    image: &geng::image::RgbaImage,
+    provenance: &Provenance,
+) -> anyhow::Result<Vec<u8>> {
+    let mut data = Vec::new();
+    {
+        let mut encoder = png::Encoder::new(&mut data, image.width(), image.height());
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.add_text_chunk(PROVENANCE_KEYWORD.to_owned(), provenance_text(provenance))?;
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(image.as_raw())?;
+        writer.finish()?;
+    }
+    Ok(data)
+}
+
+/// Container for a sequence of frames
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Format {
+    /// Zip archive with one `.png` per frame
+    PngSequence,
+    Gif,
+    Apng,
+}
+
+impl Format {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::PngSequence => "PNG sequence",
+            Self::Gif => "GIF",
+            Self::Apng => "APNG",
+        }
+    }
+}
+
+/// Looping GIF with the [Provenance] in a comment, fully transparent pixels stay transparent
+pub fn encode_gif(
+    frames: &[geng::image::RgbaImage],
+    fps: f32,
+    provenance: &Provenance,
+) -> anyhow::Result<Vec<u8>> {
+    let Some(first) = frames.first() else {
+        anyhow::bail!("no frames to encode");
+    };
+    let width = u16::try_from(first.width())?;
+    let height = u16::try_from(first.height())?;
+    let mut data = Vec::new();
+    {
+        let mut encoder = gif::Encoder::new(&mut data, width, height, &[])?;
+        encoder.set_repeat(gif::Repeat::Infinite)?;
+        encoder.write_raw_extension(
+            gif::AnyExtension(gif::Extension::Comment as u8),
+            &[provenance_text(provenance).as_bytes()],
+        )?;
+        // GIF delays are in hundredths of a second
+        let delay = (100.0 / fps).round() as u16;
+        for image in frames {
+            let mut pixels = image.as_raw().clone();
+            let mut frame = gif::Frame::from_rgba_speed(width, height, &mut pixels, 10);
+            frame.delay = delay;
+            encoder.write_frame(&frame)?;
+        }
+    }
+    Ok(data)
+}
+
+/// Looping animated PNG with the [Provenance] in a text chunk
+pub fn encode_apng(
+    frames: &[geng::image::RgbaImage],
+    fps: f32,
+    provenance: &Provenance,
+) -> anyhow::Result<Vec<u8>> {
+    let Some(first) = frames.first() else {
+        anyhow::bail!("no frames to encode");
+    };
+    let mut data = Vec::new();
+    {
+        let mut encoder = png::Encoder::new(&mut data, first.width(), first.height());
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.add_text_chunk(PROVENANCE_KEYWORD.to_owned(), provenance_text(provenance))?;
+        encoder.set_animated(frames.len() as u32, 0)?;
+        // The delay is a fraction of a second, hundredths keep fractional frame rates exact
+        let fps = (fps * 100.0).round().clamp(1.0, u16::MAX as f32) as u16;
+        encoder.set_frame_delay(100, fps)?;
+        let mut writer = encoder.write_header()?;
+        for image in frames {
+            writer.write_image_data(image.as_raw())?;
+        }
+        writer.finish()?;
+    }
+    Ok(data)
+}
+
+/// Zip archive of the frames as `{name}_000.png`, `{name}_001.png`, ...
+///
+/// The frames are stored as is, PNG data is already compressed.
+pub fn encode_png_sequence(
+    frames: &[geng::image::RgbaImage],
+    name: &str,
+    provenance: &Provenance,
+) -> anyhow::Result<Vec<u8>> {
+    use std::io::Write as _;
+
+    let mut archive = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+    let options =
+        zip::write::SimpleFileOptions::default().compression_method(zip::CompressionMethod::Stored);
+    for (index, image) in frames.iter().enumerate() {
+        archive.start_file(format!("{name}_{index:03}.png"), options)?;
+        archive.write_all(&encode_png(image, provenance)?)?;
+    }
+    Ok(archive.finish()?.into_inner())
+}
+
+/// Encode the frames into a single file named after `name`
+pub fn encode(
+    frames: &[geng::image::RgbaImage],
+    name: &str,
+    format: Format,
+    fps: f32,
+    provenance: &Provenance,
+) -> anyhow::Result<glb::File> {
+    Ok(match format {
+        Format::PngSequence => glb::File {
+            name: format!("{name}.zip"),
+            data: encode_png_sequence(frames, name, provenance)?,
+        },
+        Format::Gif => glb::File {
+            name: format!("{name}.gif"),
+            data: encode_gif(frames, fps, provenance)?,
+        },
+        Format::Apng => glb::File {
+            name: format!("{name}.png"),
+            data: encode_apng(frames, fps, provenance)?,
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn png_provenance() {
+        let image = geng::image::RgbaImage::from_pixel(4, 4, geng::image::Rgba([1, 2, 3, 4]));
+        let mut provenance = Provenance::new(&image, &Options::default());
+        provenance.source_file = Some("héros 🗡.png".to_owned());
+
+        let data = encode_png(&image, &provenance).unwrap();
+        let reader = png::Decoder::new(std::io::Cursor::new(data))
+            .read_info()
+            .unwrap();
+        let chunk = reader
+            .info()
+            .uncompressed_latin1_text
+            .iter()
+            .find(|chunk| chunk.keyword == PROVENANCE_KEYWORD)
+            .unwrap();
+        assert!(chunk.text.is_ascii());
+        let decoded: Provenance = serde_json::from_str(&chunk.text).unwrap();
+        assert_eq!(decoded.source_file, provenance.source_file);
+        assert_eq!(decoded.source_hash, provenance.source_hash);
+    }
+
+    fn frames(count: u8) -> Vec<geng::image::RgbaImage> {
+        (0..count)
+            .map(|i| geng::image::RgbaImage::from_pixel(2, 2, geng::image::Rgba([i, 0, 0, 255])))
+            .collect()
+    }
+
+    #[test]
+    fn png_sequence_is_one_archive() {
+        let provenance = Provenance::new(&frames(1)[0], &Options::default());
+        let file = encode(&frames(3), "spin", Format::PngSequence, 24.0, &provenance).unwrap();
+        assert_eq!(file.name, "spin.zip");
+
+        let mut archive = zip::ZipArchive::new(std::io::Cursor::new(file.data)).unwrap();
+        let mut names: Vec<&str> = archive.file_names().collect();
+        names.sort();
+        assert_eq!(names, ["spin_000.png", "spin_001.png", "spin_002.png"]);
+        let frame = archive.by_name("spin_002.png").unwrap();
+        let mut reader = png::Decoder::new(frame).read_info().unwrap();
+        let mut pixels = vec![0; reader.output_buffer_size()];
+        reader.next_frame(&mut pixels).unwrap();
+        assert_eq!(pixels[..4], [2, 0, 0, 255]);
+    }
+
+    #[test]
+    fn apng_keeps_fractional_fps() {
+        let provenance = Provenance::new(&frames(1)[0], &Options::default());
+        let data = encode_apng(&frames(2), 12.5, &provenance).unwrap();
+        let reader = png::Decoder::new(std::io::Cursor::new(data))
+            .read_info()
+            .unwrap();
+        let control = reader.info().frame_control.unwrap();
+        assert_eq!(
+            control.delay_num as f32 / control.delay_den as f32,
+            1.0 / 12.5
+        );
+    }
+}
@@ -0,0 +1,360 @@
+use super::*;
+
+use anyhow::Context as _;
+use rayon::prelude::*;
+use sprite_shape::{glb, metrics};
+
+struct Processed {
+    output: PathBuf,
+    triangles: usize,
+    fidelity: metrics::Fidelity,
+}
+
+const IMAGE_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "bmp", "tga", "webp", "gif"];
+
+/// Extensions of per-file override sidecars,
+/// `hero.png` is overridden by `hero.sprite-shape.json` or `hero.sprite-shape.toml`
+const SIDECAR_EXTENSIONS: [&str; 2] = ["sprite-shape.json", "sprite-shape.toml"];
+
+#[derive(clap::Args)]
+pub struct BatchArgs {
+    /// Input directories, files or glob patterns (e.g. `"sprites/**/*.png"`)
+    #[clap(required = true)]
+    pub inputs: Vec<String>,
+    /// Output directory, the directory structure of the inputs is mirrored inside
+    #[clap(short, long)]
+    pub output: PathBuf,
+    /// Output format: `glb`, `gltf` or `gltf-embedded`
+    #[clap(long, default_value = "glb", value_parser = parse_format)]
+    pub format: glb::Format,
+    /// Number of parallel jobs, defaults to the number of CPUs
+    #[clap(short, long)]
+    pub jobs: Option<usize>,
+    #[clap(flatten)]
+    pub export: cli_options::ExportArgs,
+    #[clap(flatten)]
+    pub quality: cli_options::QualityArgs,
+    #[clap(flatten)]
+    pub options: cli_options::OptionsArgs,
+}
+
+fn parse_format(s: &str) -> Result<glb::Format, String> {
+    match s {
+        "glb" => Ok(glb::Format::Glb),
+        "gltf" => Ok(glb::Format::Gltf),
+        "gltf-embedded" => Ok(glb::Format::GltfEmbedded),
+        _ => Err(format!(
+            "unknown format {s:?}, expected glb, gltf or gltf-embedded"
+        )),
+    }
+}
+
+pub struct Job {
+    pub input: PathBuf,
+    /// Path relative to the output directory
+    relative: PathBuf,
+}
+
+impl Job {
+    /// Possible sidecars with the same stem as the image, whether they exist or not
+    ///
+    /// Images with the same stem in one directory would share them, but they are rejected
+    /// by [collect_jobs] anyway since their outputs would collide too.
+    pub fn sidecars(&self) -> [PathBuf; 2] {
+        SIDECAR_EXTENSIONS.map(|extension| self.input.with_extension(extension))
+    }
+
+    /// The sidecar that exists, if any
+    pub fn sidecar(&self) -> anyhow::Result<Option<PathBuf>> {
+        let mut existing = self.sidecars().into_iter().filter(|path| path.is_file());
+        match (existing.next(), existing.next()) {
+            (Some(json), Some(toml)) => anyhow::bail!("both {json:?} and {toml:?} exist"),
+            (sidecar, _) => Ok(sidecar),
+        }
+    }
+}
+
+fn is_image(path: &std::path::Path) -> bool {
+    path.extension().is_some_and(|extension| {
+        IMAGE_EXTENSIONS.contains(&extension.to_string_lossy().to_lowercase().as_str())
+    })
+}
+
+/// Leading part of the pattern that contains no glob characters
+pub fn glob_base(pattern: &str) -> PathBuf {
+    std::path::Path::new(pattern)
+        .components()
+        .take_while(|component| {
+            !component
+                .as_os_str()
+                .to_string_lossy()
+                .contains(['*', '?', '['])
+        })
+        .collect()
+}
+
+/// Find all images matching the inputs, skipping anything inside `output`
+/// so that exported textures are not picked up as sources
+///
+/// Fails if two images would be written to the same output, like `sprites/a.png` and `props/a.png`
+/// given as separate inputs, or `hero.png` next to `hero.jpg`.
+pub fn collect_jobs(inputs: &[String], output: &std::path::Path) -> anyhow::Result<Vec<Job>> {
+    let output = std::fs::canonicalize(output).ok();
+    let mut jobs = Vec::new();
+    let mut seen = HashSet::new();
+    // Output path without extension, and the input it belongs to
+    let mut outputs: HashMap<PathBuf, PathBuf> = HashMap::new();
+    for input in inputs {
+        let path = std::path::Path::new(input);
+        let (base, pattern) = if path.is_dir() {
+            (
+                path.to_owned(),
+                path.join("**").join("*").to_string_lossy().into_owned(),
+            )
+        } else if path.is_file() {
+            (
+                path.parent().unwrap_or(std::path::Path::new("")).to_owned(),
+                glob::Pattern::escape(input),
+            )
+        } else {
+            (glob_base(input), input.clone())
+        };
+        let mut matched = false;
+        for entry in glob::glob(&pattern).with_context(|| format!("invalid pattern {input:?}"))? {
+            let file = entry?;
+            if !file.is_file() || !is_image(&file) {
+                continue;
+            }
+            if let Some(output) = &output {
+                if std::fs::canonicalize(&file).is_ok_and(|file| file.starts_with(output)) {
+                    continue;
+                }
+            }
+            matched = true;
+            if !seen.insert(file.clone()) {
+                continue;
+            }
+            let relative = file.strip_prefix(&base).unwrap_or(&file).to_owned();
+            let stem = relative.with_extension("");
+            if let Some(other) = outputs.insert(stem.clone(), file.clone()) {
+                anyhow::bail!(
+                    "{other:?} and {file:?} would both be exported as {stem:?} in the output directory",
+                );
+            }
+            jobs.push(Job {
+                relative,
+                input: file,
+            });
+        }
+        if !matched {
+            anyhow::bail!("no images found for {input:?}");
+        }
+    }
+    Ok(jobs)
+}
+
+fn process(
+    job: &Job,
+    args: &BatchArgs,
+    options: &sprite_shape::Options,
+) -> anyhow::Result<Processed> {
+    let mut options = *options;
+    if let Some(sidecar) = job.sidecar()? {
+        options = cli_options::apply_overrides(&options, &sidecar)?;
+        // Flags given explicitly on the command line still win
+        args.options.apply_flags(&mut options);
+        options.validate()?;
+    }
+    let output = args
+        .output
+        .join(&job.relative)
+        .with_extension(args.format.extension());
+    let image = convert::load_image(&job.input)?;
+    let converted = convert::convert(&image, &job.input, &options);
+    args.quality.check(&converted.fidelity).with_context(|| {
+        format!(
+            "{output:?} not written ({})",
+            cli_options::format_fidelity(&converted.fidelity),
+        )
+    })?;
+    if let Some(dir) = output.parent() {
+        std::fs::create_dir_all(dir).with_context(|| format!("failed to create {dir:?}"))?;
+    }
+    convert::write(
+        &converted.mesh,
+        &job.input,
+        &output,
+        &args.export.export_options(),
+        args.format,
+    )?;
+    Ok(Processed {
+        output,
+        triangles: converted.mesh.vertices.len() / 3,
+        fidelity: converted.fidelity,
+    })
+}
+
+pub fn thread_pool(args: &BatchArgs) -> anyhow::Result<rayon::ThreadPool> {
+    Ok(rayon::ThreadPoolBuilder::new()
+        .num_threads(args.jobs.unwrap_or(0))
+        .build()?)
+}
+
+/// Process the jobs in parallel and print a report, returning the number of failures
+pub fn run_jobs(
+    pool: &rayon::ThreadPool,
+    jobs: &[&Job],
+    args: &BatchArgs,
+    options: &sprite_shape::Options,
+) -> usize {
+    let results: Vec<anyhow::Result<Processed>> = pool.install(|| {
+        jobs.par_iter()
+            .map(|job| process(job, args, options))
+            .collect()
+    });
+
+    let mut failed = 0;
+    let mut triangles = 0;
+    for (job, result) in jobs.iter().zip(results) {
+        match result {
+            Ok(processed) => {
+                triangles += processed.triangles;
+                println!(
+                    "ok     {} -> {} ({} triangles, {})",
+                    job.input.display(),
+                    processed.output.display(),
+                    processed.triangles,
+                    cli_options::format_fidelity(&processed.fidelity),
+                );
+            }
+            Err(e) => {
+                failed += 1;
+                println!("FAILED {}: {e:#}", job.input.display());
+            }
+        }
+    }
+    println!(
+        "{} converted, {failed} failed, {triangles} triangles total",
+        jobs.len() - failed,
+    );
+    failed
+}
+
+pub fn run(args: BatchArgs) -> anyhow::Result<()> {
+    let options = args.options.resolve()?;
+    let jobs = collect_jobs(&args.inputs, &args.output)?;
+    let failed = run_jobs(
+        &thread_pool(&args)?,
+        &jobs.iter().collect::<Vec<_>>(),
+        &args,
+        &options,
+    );
+    if failed != 0 {
+        anyhow::bail!("{failed} of {} files failed", jobs.len());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Fresh directory with empty files at the given relative paths
+    fn temp_tree(name: &str, files: &[&str]) -> PathBuf {
+        let root =
+            std::env::temp_dir().join(format!("sprite-shape-batch-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        for file in files {
+            let path = root.join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, []).unwrap();
+        }
+        root
+    }
+
+    fn input(path: &std::path::Path) -> String {
+        path.to_string_lossy().into_owned()
+    }
+
+    fn relative_paths(jobs: &[Job]) -> Vec<PathBuf> {
+        let mut paths: Vec<PathBuf> = jobs.iter().map(|job| job.relative.clone()).collect();
+        paths.sort();
+        paths
+    }
+
+    #[test]
+    fn mirrors_directories() {
+        let root = temp_tree(
+            "mirror",
+            &[
+                "sprites/a.png",
+                "sprites/nested/b.PNG",
+                "sprites/notes.txt",
+                "sprites/a.sprite-shape.json",
+                "sprites/out/old.png",
+            ],
+        );
+        let sprites = root.join("sprites");
+        let jobs = collect_jobs(&[input(&sprites)], &sprites.join("out")).unwrap();
+        assert_eq!(
+            relative_paths(&jobs),
+            [
+                PathBuf::from("a.png"),
+                PathBuf::from("nested").join("b.PNG")
+            ],
+        );
+        let a = jobs.iter().find(|job| job.relative == *"a.png").unwrap();
+        assert_eq!(
+            a.sidecar().unwrap(),
+            Some(sprites.join("a.sprite-shape.json")),
+        );
+
+        let pattern = input(&sprites.join("**").join("*.png"));
+        let jobs = collect_jobs(&[pattern], &root.join("out")).unwrap();
+        assert_eq!(
+            relative_paths(&jobs),
+            [PathBuf::from("a.png"), PathBuf::from("out").join("old.png")],
+        );
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn same_file_twice_is_not_a_collision() {
+        let root = temp_tree("twice", &["sprites/a.png"]);
+        let sprites = root.join("sprites");
+        let jobs = collect_jobs(
+            &[input(&sprites), input(&sprites.join("a.png"))],
+            &root.join("out"),
+        )
+        .unwrap();
+        assert_eq!(jobs.len(), 1);
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn detects_collisions_across_inputs() {
+        let root = temp_tree("across", &["sprites/a.png", "props/a.png"]);
+        let error = collect_jobs(
+            &[input(&root.join("sprites")), input(&root.join("props"))],
+            &root.join("out"),
+        )
+        .err()
+        .unwrap()
+        .to_string();
+        assert!(error.contains("sprites"), "{error}");
+        assert!(error.contains("props"), "{error}");
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn detects_collisions_between_extensions() {
+        let root = temp_tree("extensions", &["hero.png", "hero.jpg"]);
+        let error = collect_jobs(&[input(&root)], &root.join("out"))
+            .err()
+            .unwrap()
+            .to_string();
+        assert!(error.contains("hero.png"), "{error}");
+        assert!(error.contains("hero.jpg"), "{error}");
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,203 @@
+use super::*;
+
+use anyhow::Context as _;
+
+/// Flags for every [sprite_shape::Options] field, applied on top of an optional settings file
+#[derive(clap::Args)]
+pub struct OptionsArgs {
+    /// Settings file as exported by the viewer (`.json` or `.toml`)
+    #[clap(long)]
+    pub settings: Option<PathBuf>,
+    /// Start from a built-in (pixel-art, hand-painted, coin, foliage-card, thick-prop)
+    /// or user-defined preset instead of a settings file
+    #[clap(long, conflicts_with = "settings")]
+    pub preset: Option<String>,
+    #[clap(long)]
+    pub blur_sigma: Option<f32>,
+    #[clap(long)]
+    pub cell_size: Option<usize>,
+    #[clap(long)]
+    pub iso: Option<f32>,
+    #[clap(long)]
+    pub thickness: Option<f32>,
+    /// Scaling mode, e.g. `fixed-height:1.5`
+    #[clap(long, value_parser = parse_scaling)]
+    pub scaling: Option<sprite_shape::ScalingMode>,
+    #[clap(long)]
+    pub front_face: Option<bool>,
+    #[clap(long)]
+    pub back_face: Option<bool>,
+}
+
+fn parse_scaling(s: &str) -> Result<sprite_shape::ScalingMode, String> {
+    let (mode, value) = s.split_once(':').unwrap_or((s, ""));
+    match mode {
+        "fixed-height" => value
+            .parse()
+            .map(sprite_shape::ScalingMode::FixedHeight)
+            .map_err(|e| format!("invalid height {value:?}: {e}")),
+        _ => Err(format!(
+            "unknown scaling mode {mode:?}, expected fixed-height:<height>"
+        )),
+    }
+}
+
+pub fn load_settings(path: &std::path::Path) -> anyhow::Result<sprite_shape::Options> {
+    let data = std::fs::read(path).with_context(|| format!("failed to read {path:?}"))?;
+    sprite_shape::settings::parse(
+        &data,
+        sprite_shape::settings::Format::from_file_name(&path.to_string_lossy()),
+    )
+    .with_context(|| format!("failed to parse {path:?}"))
+}
+
+/// Override fields of `options` with those present in a (possibly partial) settings file
+pub fn apply_overrides(
+    options: &sprite_shape::Options,
+    path: &std::path::Path,
+) -> anyhow::Result<sprite_shape::Options> {
+    let data = std::fs::read(path).with_context(|| format!("failed to read {path:?}"))?;
+    sprite_shape::settings::apply(
+        options,
+        &data,
+        sprite_shape::settings::Format::from_file_name(&path.to_string_lossy()),
+    )
+    .with_context(|| format!("invalid overrides in {path:?}"))
+}
+
+impl OptionsArgs {
+    /// Settings file, preset or defaults with flags applied on top
+    pub fn resolve(&self) -> anyhow::Result<sprite_shape::Options> {
+        let mut options = match (&self.settings, &self.preset) {
+            (Some(path), _) => load_settings(path)?,
+            (None, Some(preset)) => user_presets::find(preset)?,
+            (None, None) => sprite_shape::Options::default(),
+        };
+        self.apply_flags(&mut options);
+        options.validate()?;
+        Ok(options)
+    }
+
+    pub fn apply_flags(&self, options: &mut sprite_shape::Options) {
+        macro_rules! options {
+            ($($op:ident,)*) => {
+                $(if let Some($op) = self.$op {
+                    options.$op = $op;
+                })*
+            }
+        }
+        options! {
+            blur_sigma,
+            cell_size,
+            iso,
+            thickness,
+            scaling,
+            front_face,
+            back_face,
+        };
+    }
+}
+
+/// Material and sampler settings of the exported glTF
+#[derive(clap::Args)]
+pub struct ExportArgs {
+    /// Texture filtering: `linear`, or `nearest` to keep pixel art crisp
+    #[clap(long, default_value = "linear", value_parser = parse_filter)]
+    pub filter: sprite_shape::glb::TextureFilter,
+    /// Repeat the texture instead of clamping texture coordinates to the edge
+    #[clap(long)]
+    pub repeat: bool,
+    /// Material alpha mode: `opaque`, `mask` or `blend`
+    #[clap(long, default_value = "opaque", value_parser = parse_alpha_mode)]
+    pub alpha_mode: sprite_shape::glb::AlphaMode,
+    /// Texels with lower alpha are discarded with `--alpha-mode mask`
+    #[clap(long, default_value_t = 0.5)]
+    pub alpha_cutoff: f32,
+    #[clap(long)]
+    pub double_sided: bool,
+}
+
+fn parse_filter(s: &str) -> Result<sprite_shape::glb::TextureFilter, String> {
+    match s {
+        "linear" => Ok(sprite_shape::glb::TextureFilter::Linear),
+        "nearest" => Ok(sprite_shape::glb::TextureFilter::Nearest),
+        _ => Err(format!("unknown filter {s:?}, expected linear or nearest")),
+    }
+}
+
+fn parse_alpha_mode(s: &str) -> Result<sprite_shape::glb::AlphaMode, String> {
+    match s {
+        "opaque" => Ok(sprite_shape::glb::AlphaMode::Opaque),
+        "mask" => Ok(sprite_shape::glb::AlphaMode::Mask),
+        "blend" => Ok(sprite_shape::glb::AlphaMode::Blend),
+        _ => Err(format!(
+            "unknown alpha mode {s:?}, expected opaque, mask or blend"
+        )),
+    }
+}
+
+impl ExportArgs {
+    pub fn export_options(&self) -> sprite_shape::glb::ExportOptions {
+        sprite_shape::glb::ExportOptions {
+            filter: self.filter,
+            clamp: !self.repeat,
+            alpha_mode: self.alpha_mode,
+            alpha_cutoff: self.alpha_cutoff,
+            double_sided: self.double_sided,
+        }
+    }
+}
+
+/// Silhouette fidelity requirements, exported meshes that don't meet them are reported as failures
+#[derive(clap::Args)]
+pub struct QualityArgs {
+    /// Fail if the silhouette IoU with the source image is lower than this
+    #[clap(long)]
+    pub min_iou: Option<f32>,
+    /// Fail if the silhouette Hausdorff distance to the source image exceeds this many pixels
+    #[clap(long)]
+    pub max_hausdorff: Option<f32>,
+}
+
+impl QualityArgs {
+    pub fn check(&self, fidelity: &sprite_shape::metrics::Fidelity) -> anyhow::Result<()> {
+        if let Some(min_iou) = self.min_iou {
+            if fidelity.iou < min_iou {
+                anyhow::bail!("IoU {:.4} is below {min_iou}", fidelity.iou);
+            }
+        }
+        if let Some(max_hausdorff) = self.max_hausdorff {
+            if fidelity.hausdorff > max_hausdorff {
+                anyhow::bail!(
+                    "Hausdorff distance {:.1}px exceeds {max_hausdorff}px",
+                    fidelity.hausdorff,
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+/// One line summary of silhouette fidelity
+pub fn format_fidelity(fidelity: &sprite_shape::metrics::Fidelity) -> String {
+    format!(
+        "IoU {:.4}, Hausdorff {:.1}px, lost {}px, added {}px",
+        fidelity.iou, fidelity.hausdorff, fidelity.lost_area, fidelity.added_area,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scaling_flag() {
+        assert_eq!(
+            parse_scaling("fixed-height:1.5"),
+            Ok(sprite_shape::ScalingMode::FixedHeight(1.5)),
+        );
+        assert!(parse_scaling("fixed-height").is_err());
+        assert!(parse_scaling("fixed-height:tall").is_err());
+        assert!(parse_scaling("fixed-width:2").is_err());
+    }
+}
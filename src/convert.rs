@@ -0,0 +1,205 @@
+use super::*;
+
+use anyhow::Context as _;
+use sprite_shape::{glb, metrics, tune};
+
+#[derive(clap::Args)]
+pub struct ConvertArgs {
+    /// Source image
+    input: PathBuf,
+    /// Output `.glb` or `.gltf` file, defaults to the input with `.glb` extension
+    #[clap(short, long)]
+    output: Option<PathBuf>,
+    /// Embed buffer and texture into the `.gltf` as data URIs instead of writing them separately
+    #[clap(long)]
+    embed: bool,
+    /// Pick cell_size and blur_sigma automatically to get the best silhouette
+    /// with at most this many triangles
+    #[clap(long, conflicts_with = "max_error")]
+    target_triangles: Option<usize>,
+    /// Pick cell_size and blur_sigma automatically to get the fewest triangles
+    /// with at most this silhouette error (1 - IoU)
+    #[clap(long)]
+    max_error: Option<f32>,
+    #[clap(flatten)]
+    export: cli_options::ExportArgs,
+    #[clap(flatten)]
+    quality: cli_options::QualityArgs,
+    #[clap(flatten)]
+    options: cli_options::OptionsArgs,
+}
+
+pub struct Converted {
+    pub mesh: sprite_shape::SpriteMesh,
+    pub fidelity: metrics::Fidelity,
+}
+
+/// Pick the export format based on output file extension
+pub fn format_for(output: &std::path::Path, embed: bool) -> anyhow::Result<glb::Format> {
+    let extension = output
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase());
+    Ok(match extension.as_deref() {
+        Some("glb") => glb::Format::Glb,
+        Some("gltf") if embed => glb::Format::GltfEmbedded,
+        Some("gltf") => glb::Format::Gltf,
+        _ => anyhow::bail!("unsupported output {output:?}, expected .glb or .gltf"),
+    })
+}
+
+pub fn load_image(path: &std::path::Path) -> anyhow::Result<geng::image::RgbaImage> {
+    Ok(geng::image::open(path)
+        .with_context(|| format!("failed to load {path:?}"))?
+        .into_rgba8())
+}
+
+/// Generate the mesh for the image loaded from `input` and measure its fidelity,
+/// nothing is written yet so that rejected meshes never end up on disk
+pub fn convert(
+    image: &geng::image::RgbaImage,
+    input: &std::path::Path,
+    options: &sprite_shape::Options,
+) -> Converted {
+    let mut mesh = sprite_shape::SpriteMesh::new(image, options);
+    mesh.provenance.source_file = input
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned());
+    let fidelity = metrics::Fidelity::new(image, &mesh.vertices);
+    Converted { mesh, fidelity }
+}
+
+/// Export the mesh to `output`, together with any separate files next to it
+///
+/// Nothing is written if any of the files would replace the `input` image.
+pub fn write(
+    mesh: &sprite_shape::SpriteMesh,
+    input: &std::path::Path,
+    output: &std::path::Path,
+    export_options: &glb::ExportOptions,
+    format: glb::Format,
+) -> anyhow::Result<()> {
+    let name = output
+        .file_stem()
+        .with_context(|| format!("invalid output path {output:?}"))?
+        .to_string_lossy();
+    let dir = output.parent().unwrap_or(std::path::Path::new(""));
+    let files: Vec<(PathBuf, Vec<u8>)> = glb::export(mesh, &name, export_options, format)?
+        .into_iter()
+        .map(|file| (dir.join(file.name), file.data))
+        .collect();
+    let input =
+        std::fs::canonicalize(input).with_context(|| format!("failed to resolve {input:?}"))?;
+    for (path, _) in &files {
+        if std::fs::canonicalize(path).is_ok_and(|path| path == input) {
+            anyhow::bail!("refusing to overwrite the input image with {path:?}");
+        }
+    }
+    for (path, data) in files {
+        std::fs::write(&path, data).with_context(|| format!("failed to write {path:?}"))?;
+    }
+    Ok(())
+}
+
+pub fn run(args: ConvertArgs) -> anyhow::Result<()> {
+    let mut options = args.options.resolve()?;
+    let output = args
+        .output
+        .unwrap_or_else(|| args.input.with_extension("glb"));
+    let format = format_for(&output, args.embed)?;
+    let image = load_image(&args.input)?;
+    let target = match (args.target_triangles, args.max_error) {
+        (Some(triangles), _) => Some(tune::Target::Triangles(triangles)),
+        (None, Some(error)) => Some(tune::Target::MaxError(error)),
+        (None, None) => None,
+    };
+    if let Some(target) = target {
+        let tuned = tune::tune(&image, &options, target);
+        println!(
+            "auto: cell_size = {}, blur_sigma = {}: {} triangles, silhouette error {:.4}{}",
+            tuned.options.cell_size,
+            tuned.options.blur_sigma,
+            tuned.triangles,
+            tuned.error,
+            if tuned.reached {
+                ""
+            } else {
+                " (target not reached)"
+            },
+        );
+        options = tuned.options;
+    }
+    let converted = convert(&image, &args.input, &options);
+    let fidelity = cli_options::format_fidelity(&converted.fidelity);
+    args.quality
+        .check(&converted.fidelity)
+        .with_context(|| format!("{} not written ({fidelity})", output.display()))?;
+    write(
+        &converted.mesh,
+        &args.input,
+        &output,
+        &args.export.export_options(),
+        format,
+    )?;
+    println!(
+        "{} -> {} ({} triangles, {fidelity})",
+        args.input.display(),
+        output.display(),
+        converted.mesh.vertices.len() / 3,
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn square_image() -> geng::image::RgbaImage {
+        geng::image::RgbaImage::from_fn(32, 32, |x, y| {
+            let inside = (8..24).contains(&x) && (8..24).contains(&y);
+            geng::image::Rgba([255, 0, 0, if inside { u8::MAX } else { 0 }])
+        })
+    }
+
+    #[test]
+    fn gltf_next_to_the_input_keeps_it() {
+        let dir = std::env::temp_dir().join(format!("sprite-shape-convert-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("hero.png");
+        square_image().save(&input).unwrap();
+        let source = std::fs::read(&input).unwrap();
+
+        let image = load_image(&input).unwrap();
+        let converted = convert(&image, &input, &sprite_shape::Options::default());
+        let export_options = glb::ExportOptions::default();
+        let output = dir.join("hero.gltf");
+        write(
+            &converted.mesh,
+            &input,
+            &output,
+            &export_options,
+            glb::Format::Gltf,
+        )
+        .unwrap();
+        assert_eq!(std::fs::read(&input).unwrap(), source);
+        for file in ["hero.gltf", "hero.bin", "hero_texture.png"] {
+            assert!(dir.join(file).is_file(), "{file} was not written");
+        }
+
+        // An input that is named like one of the outputs is refused before anything is written
+        let input = dir.join("sprite_texture.png");
+        std::fs::copy(dir.join("hero.png"), &input).unwrap();
+        let output = dir.join("sprite.gltf");
+        assert!(write(
+            &converted.mesh,
+            &input,
+            &output,
+            &export_options,
+            glb::Format::Gltf,
+        )
+        .is_err());
+        assert_eq!(std::fs::read(&input).unwrap(), source);
+        assert!(!output.exists());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,218 @@
+use super::*;
+
+use anyhow::Context as _;
+use notify::Watcher as _;
+
+/// Directory of `path`, `.` for a bare file name
+pub fn parent_dir(path: &std::path::Path) -> PathBuf {
+    match path.parent() {
+        Some(parent) if parent != std::path::Path::new("") => parent.to_owned(),
+        _ => PathBuf::from("."),
+    }
+}
+
+/// Canonical path, so that paths from events and from the command line can be compared
+///
+/// Deleted files no longer resolve, then their directory is canonicalized instead.
+pub fn canonical(path: &std::path::Path) -> PathBuf {
+    if let Ok(path) = std::fs::canonicalize(path) {
+        return path;
+    }
+    match path.file_name() {
+        Some(name) if path.parent().is_some() => canonical(&parent_dir(path)).join(name),
+        _ => std::path::absolute(path).unwrap_or_else(|_| path.to_owned()),
+    }
+}
+
+/// Reports changes to watched files and directories, once they stop changing for a while
+pub struct FileWatcher {
+    watcher: notify::RecommendedWatcher,
+    receiver: std::sync::mpsc::Receiver<notify::Result<notify::Event>>,
+    /// Canonical paths of the watched files mapped to the paths they were registered with
+    files: HashMap<PathBuf, PathBuf>,
+    /// Canonical watched directories, and whether their subdirectories are watched too
+    dirs: Vec<(PathBuf, bool)>,
+    pending: HashSet<PathBuf>,
+    last_change: Option<std::time::Instant>,
+    debounce: std::time::Duration,
+}
+
+impl FileWatcher {
+    pub fn new(debounce: std::time::Duration) -> anyhow::Result<Self> {
+        let (sender, receiver) = std::sync::mpsc::channel();
+        Ok(Self {
+            watcher: notify::recommended_watcher(sender)?,
+            receiver,
+            files: default(),
+            dirs: Vec::new(),
+            pending: default(),
+            last_change: None,
+            debounce,
+        })
+    }
+
+    /// Watch a single file, its changes are reported with the path given here
+    pub fn watch(&mut self, path: &std::path::Path) -> anyhow::Result<()> {
+        let canonical =
+            std::fs::canonicalize(path).with_context(|| format!("failed to watch {path:?}"))?;
+        // Editors often replace the file instead of writing into it,
+        // so watch the directory rather than the file itself
+        let dir = canonical.parent().unwrap_or(&canonical);
+        self.watcher
+            .watch(dir, notify::RecursiveMode::NonRecursive)
+            .with_context(|| format!("failed to watch {dir:?}"))?;
+        self.files.insert(canonical, path.to_owned());
+        Ok(())
+    }
+
+    /// Watch every file in a directory, their changes are reported as [canonical] paths
+    pub fn watch_dir(&mut self, path: &std::path::Path, recursive: bool) -> anyhow::Result<()> {
+        let mode = if recursive {
+            notify::RecursiveMode::Recursive
+        } else {
+            notify::RecursiveMode::NonRecursive
+        };
+        self.watcher
+            .watch(path, mode)
+            .with_context(|| format!("failed to watch {path:?}"))?;
+        self.dirs.push((canonical(path), recursive));
+        Ok(())
+    }
+
+    fn handle(&mut self, event: notify::Result<notify::Event>) {
+        let event = match event {
+            Ok(event) => event,
+            Err(e) => {
+                log::warn!("watch error: {e}");
+                return;
+            }
+        };
+        // Removals matter too, a deleted sidecar changes the options of its image
+        if !matches!(
+            event.kind,
+            notify::EventKind::Create(_)
+                | notify::EventKind::Modify(_)
+                | notify::EventKind::Remove(_)
+        ) {
+            return;
+        }
+        for path in event.paths {
+            let path = canonical(&path);
+            let changed = if let Some(original) = self.files.get(&path) {
+                original.clone()
+            } else if self.dirs.iter().any(|(dir, recursive)| {
+                if *recursive {
+                    path.starts_with(dir)
+                } else {
+                    path.parent() == Some(dir.as_path())
+                }
+            }) {
+                path
+            } else {
+                continue;
+            };
+            self.pending.insert(changed);
+            self.last_change = Some(std::time::Instant::now());
+        }
+    }
+
+    fn take_quiet(&mut self) -> Vec<PathBuf> {
+        match self.last_change {
+            Some(time) if time.elapsed() >= self.debounce => {
+                self.last_change = None;
+                self.pending.drain().collect()
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// Files that have changed since the last call and have been quiet for the debounce period
+    pub fn poll(&mut self) -> Vec<PathBuf> {
+        while let Ok(event) = self.receiver.try_recv() {
+            self.handle(event);
+        }
+        self.take_quiet()
+    }
+
+    /// Like [Self::poll], but blocks until there are changes
+    pub fn wait(&mut self) -> anyhow::Result<Vec<PathBuf>> {
+        loop {
+            match self.last_change {
+                None => {
+                    let event = self.receiver.recv()?;
+                    self.handle(event);
+                }
+                Some(time) => {
+                    let timeout = self.debounce.saturating_sub(time.elapsed());
+                    match self.receiver.recv_timeout(timeout) {
+                        Ok(event) => self.handle(event),
+                        Err(std::sync::mpsc::RecvTimeoutError::Timeout) => {}
+                        Err(e) => return Err(e.into()),
+                    }
+                }
+            }
+            let changed = self.take_quiet();
+            if !changed.is_empty() {
+                return Ok(changed);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn canonical_deleted_file() {
+        let dir = std::env::temp_dir().join(format!("sprite-shape-watch-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("hero.sprite-shape.json");
+        std::fs::write(&file, "{}").unwrap();
+        let existing = canonical(&file);
+        std::fs::remove_file(&file).unwrap();
+        assert_eq!(canonical(&file), existing);
+        // Through a path that only resolves after normalization
+        let indirect = dir
+            .join("..")
+            .join(dir.file_name().unwrap())
+            .join("hero.sprite-shape.json");
+        assert_eq!(canonical(&indirect), existing);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn reports_changes_in_watched_dirs() {
+        let dir =
+            std::env::temp_dir().join(format!("sprite-shape-file-watcher-{}", std::process::id()));
+        let nested = dir.join("nested");
+        std::fs::create_dir_all(&nested).unwrap();
+        let file = dir.join("settings.json");
+        std::fs::write(&file, "{}").unwrap();
+
+        let mut watcher = FileWatcher::new(std::time::Duration::from_millis(50)).unwrap();
+        watcher.watch(&file).unwrap();
+        watcher.watch_dir(&nested, false).unwrap();
+        std::fs::write(&file, "{\"iso\": 0.3}").unwrap();
+        std::fs::write(nested.join("hero.png"), "").unwrap();
+        std::fs::write(dir.join("unrelated.txt"), "").unwrap();
+
+        let mut changed = HashSet::new();
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+        while changed.len() < 2 && std::time::Instant::now() < deadline {
+            changed.extend(watcher.wait().unwrap());
+        }
+        assert_eq!(
+            changed,
+            HashSet::from([file.clone(), canonical(&nested.join("hero.png"))]),
+        );
+
+        // Deleting a file is a change too
+        std::fs::remove_file(nested.join("hero.png")).unwrap();
+        assert_eq!(
+            watcher.wait().unwrap(),
+            [canonical(&nested.join("hero.png"))]
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,182 @@
+use super::*;
+
+use sprite_shape::{inspect, metrics, tune};
+
+pub struct Request {
+    pub image: Arc<geng::image::RgbaImage>,
+    /// File name of the image, recorded in the mesh provenance
+    pub source_file: Option<String>,
+    pub options: sprite_shape::Options,
+    /// Also compute the intermediate results for the inspection view
+    pub inspect: bool,
+}
+
+pub struct Generated {
+    /// Image the mesh was generated from
+    pub image: Arc<geng::image::RgbaImage>,
+    pub mesh: sprite_shape::SpriteMesh,
+    pub fidelity: metrics::Fidelity,
+    pub inspection: Option<inspect::Inspection>,
+}
+
+pub fn generate(request: &Request, cancelled: &dyn Fn() -> bool) -> Option<Generated> {
+    let mut mesh =
+        sprite_shape::SpriteMesh::new_cancellable(&request.image, &request.options, cancelled)?;
+    mesh.provenance.source_file = request.source_file.clone();
+    if cancelled() {
+        return None;
+    }
+    let fidelity = metrics::Fidelity::new(&request.image, &mesh.vertices);
+    let inspection = request
+        .inspect
+        .then(|| inspect::Inspection::new(&request.image, &request.options));
+    Some(Generated {
+        image: request.image.clone(),
+        mesh,
+        fidelity,
+        inspection,
+    })
+}
+
+pub struct TuneRequest {
+    pub image: Arc<geng::image::RgbaImage>,
+    pub options: sprite_shape::Options,
+    pub target: tune::Target,
+}
+
+pub fn tune(request: &TuneRequest, cancelled: &dyn Fn() -> bool) -> Option<tune::Tuned> {
+    tune::tune_cancellable(&request.image, &request.options, request.target, cancelled)
+}
+
+/// Job run by a [Worker], returns `None` once it notices it was cancelled
+pub type Job<Request, Output> = fn(&Request, &dyn Fn() -> bool) -> Option<Output>;
+
+pub type Generator = Worker<Request, Generated>;
+
+/// Runs a job on a worker thread, only the result of the latest request is ever returned
+#[cfg(not(target_arch = "wasm32"))]
+pub struct Worker<Request, Output> {
+    requests: std::sync::mpsc::Sender<(u64, Request)>,
+    results: std::sync::mpsc::Receiver<(u64, anyhow::Result<Output>)>,
+    /// Id of the latest request, anything older is stale
+    latest: Arc<std::sync::atomic::AtomicU64>,
+    busy: bool,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl<Request: Send + 'static, Output: Send + 'static> Worker<Request, Output> {
+    pub fn spawn(job: Job<Request, Output>) -> Self {
+        use std::sync::atomic::Ordering;
+
+        let (requests, worker_requests) = std::sync::mpsc::channel::<(u64, Request)>();
+        let (worker_results, results) = std::sync::mpsc::channel();
+        let latest = Arc::new(std::sync::atomic::AtomicU64::new(0));
+        let worker_latest = latest.clone();
+        std::thread::spawn(move || {
+            while let Ok(mut next) = worker_requests.recv() {
+                // Skip straight to the newest request if several piled up
+                while let Ok(newer) = worker_requests.try_recv() {
+                    next = newer;
+                }
+                let (id, request) = next;
+                let cancelled = || worker_latest.load(Ordering::Relaxed) != id;
+                // Options are validated before they are sent here, this is a last resort so
+                // that a bug in generation is reported instead of taking the worker down
+                let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                    job(&request, &cancelled)
+                }));
+                let result = match result {
+                    Ok(None) => continue,
+                    Ok(Some(output)) => Ok(output),
+                    Err(panic) => Err(anyhow::anyhow!(
+                        "generation failed: {}",
+                        panic_message(panic.as_ref())
+                    )),
+                };
+                if worker_results.send((id, result)).is_err() {
+                    break;
+                }
+            }
+        });
+        Self {
+            requests,
+            results,
+            latest,
+            busy: false,
+        }
+    }
+
+    /// Start the job, cancelling whatever is in progress
+    pub fn request(&mut self, request: Request) {
+        let id = self
+            .latest
+            .fetch_add(1, std::sync::atomic::Ordering::Relaxed)
+            + 1;
+        self.busy = self.requests.send((id, request)).is_ok();
+    }
+
+    /// Stop the job in progress without starting another one
+    pub fn cancel(&mut self) {
+        self.latest
+            .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        self.busy = false;
+    }
+
+    /// Result of the latest request, once it is done
+    pub fn poll(&mut self) -> Option<anyhow::Result<Output>> {
+        let latest = self.latest.load(std::sync::atomic::Ordering::Relaxed);
+        let mut result = None;
+        while let Ok((id, output)) = self.results.try_recv() {
+            if id == latest {
+                result = Some(output);
+                self.busy = false;
+            }
+        }
+        result
+    }
+
+    pub fn is_busy(&self) -> bool {
+        self.busy
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn panic_message(panic: &(dyn std::any::Any + Send)) -> &str {
+    if let Some(message) = panic.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = panic.downcast_ref::<String>() {
+        message
+    } else {
+        "unknown panic"
+    }
+}
+
+/// There are no threads on the web, so the job runs right away
+#[cfg(target_arch = "wasm32")]
+pub struct Worker<Request, Output> {
+    job: Job<Request, Output>,
+    result: Option<Output>,
+}
+
+#[cfg(target_arch = "wasm32")]
+impl<Request, Output> Worker<Request, Output> {
+    pub fn spawn(job: Job<Request, Output>) -> Self {
+        Self { job, result: None }
+    }
+
+    pub fn request(&mut self, request: Request) {
+        self.result = (self.job)(&request, &|| false);
+    }
+
+    pub fn cancel(&mut self) {
+        self.result = None;
+    }
+
+    pub fn poll(&mut self) -> Option<anyhow::Result<Output>> {
+        self.result.take().map(Ok)
+    }
+
+    pub fn is_busy(&self) -> bool {
+        false
+    }
+}
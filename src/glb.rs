@@ -10,9 +10,55 @@ use std::borrow::Cow;
 #[repr(C)]
 struct Vertex {
     position: [f32; 3],
+    normal: [f32; 3],
     uv: [f32; 2],
 }
 
+impl Vertex {
+    fn key(&self) -> [u32; 8] {
+        let [x, y, z] = self.position;
+        let [nx, ny, nz] = self.normal;
+        let [u, v] = self.uv;
+        [x, y, z, nx, ny, nz, u, v].map(f32::to_bits)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TextureFilter {
+    Nearest,
+    Linear,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AlphaMode {
+    Opaque,
+    Mask,
+    Blend,
+}
+
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+pub struct ExportOptions {
+    pub filter: TextureFilter,
+    /// Clamp texture coordinates to edge instead of repeating
+    pub clamp: bool,
+    pub alpha_mode: AlphaMode,
+    /// Only used with [AlphaMode::Mask]
+    pub alpha_cutoff: f32,
+    pub double_sided: bool,
+}
+
+impl Default for ExportOptions {
+    fn default() -> Self {
+        Self {
+            filter: TextureFilter::Linear,
+            clamp: true,
+            alpha_mode: AlphaMode::Opaque,
+            alpha_cutoff: 0.5,
+            double_sided: false,
+        }
+    }
+}
+
 /// Calculate bounding coordinates of a list of vertices, used for the clipping distance of the model
 fn bounding_coords(points: &[Vertex]) -> ([f32; 3], [f32; 3]) {
     let mut min = [f32::MAX, f32::MAX, f32::MAX];
@@ -28,10 +74,34 @@ fn bounding_coords(points: &[Vertex]) -> ([f32; 3], [f32; 3]) {
     (min, max)
 }
 
+/// Merge identical vertices, producing an index buffer
+fn deduplicate(vertices: impl IntoIterator<Item = Vertex>) -> (Vec<Vertex>, Vec<u32>) {
+    let mut unique = Vec::new();
+    let mut lookup = HashMap::new();
+    let indices = vertices
+        .into_iter()
+        .map(|vertex| {
+            *lookup.entry(vertex.key()).or_insert_with(|| {
+                unique.push(vertex);
+                unique.len() as u32 - 1
+            })
+        })
+        .collect();
+    (unique, indices)
+}
+
 fn align_to_multiple_of_four(n: &mut usize) {
     *n = (*n + 3) & !3;
 }
 
+fn wrapping_mode(clamp: bool) -> json::texture::WrappingMode {
+    if clamp {
+        json::texture::WrappingMode::ClampToEdge
+    } else {
+        json::texture::WrappingMode::Repeat
+    }
+}
+
 fn to_padded_byte_vector<T>(vec: Vec<T>) -> Vec<u8> {
     let byte_length = vec.len() * mem::size_of::<T>();
     let byte_capacity = vec.capacity() * mem::size_of::<T>();
@@ -44,36 +114,228 @@ fn to_padded_byte_vector<T>(vec: Vec<T>) -> Vec<u8> {
     new_vec
 }
 
-pub fn save(ugli: &Ugli, sprite: &sprite_shape::ThickSprite<viewer::Vertex>) -> Vec<u8> {
-    let vertices: Vec<Vertex> = sprite
-        .mesh
-        .iter()
-        .map(|vertex| Vertex {
-            position: **vertex.a_pos,
-            uv: **vertex.a_uv,
+/// Encode the image as PNG
+pub fn encode_png(image: &geng::image::RgbaImage) -> Vec<u8> {
+    let mut writer = std::io::Cursor::new(Vec::new());
+    image
+        .write_to(&mut writer, geng::image::ImageFormat::Png)
+        .unwrap();
+    writer.into_inner()
+}
+
+fn data_uri(mime_type: &str, data: &[u8]) -> String {
+    use base64::Engine;
+    format!(
+        "data:{mime_type};base64,{}",
+        base64::engine::general_purpose::STANDARD.encode(data),
+    )
+}
+
+/// Percent-encode everything except unreserved characters
+fn uri_escape(s: &str) -> String {
+    s.bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                char::from(byte).to_string()
+            }
+            _ => format!("%{byte:02X}"),
         })
-        .collect();
-    let vertex_count = vertices.len();
+        .collect()
+}
 
-    let image = {
-        let texture = &sprite.texture;
-        let framebuffer =
-            ugli::FramebufferRead::new_color(ugli, ugli::ColorAttachmentRead::Texture(texture));
-        let data = framebuffer.read_color();
-        let image = geng::image::RgbaImage::from_vec(
-            texture.size().x as _,
-            texture.size().y as _,
-            data.data().to_vec(),
-        )
-        .unwrap();
-        image
+/// Where the texture image ends up
+enum ImageSource<'a> {
+    /// Appended to the binary buffer
+    Buffer(&'a [u8]),
+    Uri(String),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Format {
+    /// Single binary `.glb` file
+    Glb,
+    /// `.gltf` with external `.bin` and `_texture.png` files
+    Gltf,
+    /// Single `.gltf` with buffer and texture embedded as data URIs
+    GltfEmbedded,
+}
+
+impl Format {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Glb => "glb",
+            Self::Gltf | Self::GltfEmbedded => "gltf",
+        }
+    }
+}
+
+/// A single output file of the export
+pub struct File {
+    pub name: String,
+    pub data: Vec<u8>,
+}
+
+/// Export the sprite in the given format
+///
+/// `name` is used for output file names and for the scene, node, mesh and material
+/// (usually the source file stem). The main file always comes first.
+pub fn export(
+    mesh: &SpriteMesh,
+    name: &str,
+    options: &ExportOptions,
+    format: Format,
+) -> anyhow::Result<Vec<File>> {
+    let main_file = format!("{name}.{}", format.extension());
+    Ok(match format {
+        Format::Glb => vec![File {
+            name: main_file,
+            data: save(mesh, Some(name), options)?,
+        }],
+        Format::Gltf => save_gltf(mesh, name, options)?,
+        Format::GltfEmbedded => vec![File {
+            name: main_file,
+            data: save_gltf_embedded(mesh, Some(name), options)?,
+        }],
+    })
+}
+
+/// Serialize the sprite as a binary glTF
+///
+/// `name` is used for the scene, node, mesh and material (usually the source file stem)
+pub fn save(
+    mesh: &SpriteMesh,
+    name: Option<&str>,
+    options: &ExportOptions,
+) -> anyhow::Result<Vec<u8>> {
+    let png = encode_png(&mesh.texture);
+    let (root, all_data) = build(mesh, name, options, ImageSource::Buffer(&png))?;
+
+    let json_string = json::serialize::to_string(&root).expect("Serialization error");
+    let mut json_offset = json_string.len();
+    align_to_multiple_of_four(&mut json_offset);
+    let all_data = to_padded_byte_vector(all_data);
+    let glb = gltf::binary::Glb {
+        header: gltf::binary::Header {
+            magic: *b"glTF",
+            version: 2,
+            // N.B., the size of binary glTF file is limited to range of `u32`.
+            length: (json_offset + all_data.len())
+                .try_into()
+                .expect("file size exceeds binary glTF limit"),
+        },
+        bin: Some(Cow::Owned(all_data)),
+        json: Cow::Owned(json_string.into_bytes()),
+    };
+    Ok(glb.to_vec().expect("glTF binary output error"))
+}
+
+/// Serialize the sprite as `name.gltf` referencing `name.bin` and `name_texture.png`
+///
+/// The texture is not called `name.png`, which usually is the source image.
+pub fn save_gltf(
+    mesh: &SpriteMesh,
+    name: &str,
+    options: &ExportOptions,
+) -> anyhow::Result<Vec<File>> {
+    let png = encode_png(&mesh.texture);
+    let bin_file = format!("{name}.bin");
+    let png_file = format!("{name}_texture.png");
+    let (mut root, buffer) = build(
+        mesh,
+        Some(name),
+        options,
+        ImageSource::Uri(uri_escape(&png_file)),
+    )?;
+    root.buffers[0].uri = Some(uri_escape(&bin_file));
+    let json_string = json::serialize::to_string_pretty(&root).expect("Serialization error");
+    Ok(vec![
+        File {
+            name: format!("{name}.gltf"),
+            data: json_string.into_bytes(),
+        },
+        File {
+            name: bin_file,
+            data: buffer,
+        },
+        File {
+            name: png_file,
+            data: png,
+        },
+    ])
+}
+
+/// Serialize the sprite as a self-contained `.gltf` with data URIs
+pub fn save_gltf_embedded(
+    mesh: &SpriteMesh,
+    name: Option<&str>,
+    options: &ExportOptions,
+) -> anyhow::Result<Vec<u8>> {
+    let png = encode_png(&mesh.texture);
+    let (mut root, buffer) = build(
+        mesh,
+        name,
+        options,
+        ImageSource::Uri(data_uri("image/png", &png)),
+    )?;
+    root.buffers[0].uri = Some(data_uri("application/octet-stream", &buffer));
+    Ok(json::serialize::to_string_pretty(&root)
+        .expect("Serialization error")
+        .into_bytes())
+}
+
+/// Key of the [Provenance] object inside the asset extras
+const EXTRAS_KEY: &str = "sprite_shape";
+
+/// Read back the [Provenance] embedded into a `.glb` or `.gltf` file by this exporter
+pub fn load_provenance(data: &[u8]) -> anyhow::Result<Provenance> {
+    let gltf = gltf::Gltf::from_slice_without_validation(data)?;
+    let Some(extras) = &gltf.as_json().asset.extras else {
+        anyhow::bail!("file has no asset extras");
+    };
+    let mut extras: serde_json::Map<String, serde_json::Value> =
+        serde_json::from_str(extras.get())?;
+    let Some(provenance) = extras.remove(EXTRAS_KEY) else {
+        anyhow::bail!("file was not generated by {}", env!("CARGO_PKG_NAME"));
     };
+    Ok(serde_json::from_value(provenance)?)
+}
+
+/// Build the glTF document, returning it together with the contents of its only buffer
+///
+/// An empty mesh is an error, glTF has no valid accessors or bounds for zero vertices.
+fn build(
+    mesh: &SpriteMesh,
+    name: Option<&str>,
+    options: &ExportOptions,
+    image_source: ImageSource,
+) -> anyhow::Result<(json::Root, Vec<u8>)> {
+    anyhow::ensure!(
+        !mesh.vertices.is_empty(),
+        "the mesh is empty, no part of the image is more opaque than iso {}",
+        mesh.provenance.options.iso,
+    );
+    let (vertices, indices) = deduplicate(mesh.vertices.iter().map(|vertex| Vertex {
+        position: **vertex.a_pos,
+        normal: **vertex.a_normal,
+        // glTF has the texture origin in the top left corner
+        uv: [vertex.a_uv.x, 1.0 - vertex.a_uv.y],
+    }));
+    let vertex_count = vertices.len();
+    let index_count = indices.len();
+    let name = name.map(|name| name.to_owned());
 
     let (min, max) = bounding_coords(&vertices);
     let mut root = gltf_json::Root::default();
+    root.asset.generator = Some(mesh.provenance.generator.clone());
+    root.asset.extras = Some(
+        serde_json::value::to_raw_value(&BTreeMap::from([(EXTRAS_KEY, &mesh.provenance)]))
+            .expect("Serialization error"),
+    );
 
     let vertex_data_start;
     let vertex_data_end;
+    let index_data_start;
+    let index_data_end;
     let texture_data_start;
     let texture_data_end;
     let all_data = {
@@ -84,11 +346,17 @@ pub fn save(ugli: &Ugli, sprite: &sprite_shape::ThickSprite<viewer::Vertex>) ->
             vertex_data_end = writer.position();
         }
         {
+            index_data_start = writer.position();
+            writer.write_all(&to_padded_byte_vector(indices)).unwrap();
+            index_data_end = writer.position();
+        }
+        if let ImageSource::Buffer(png) = image_source {
             texture_data_start = writer.position();
-            image
-                .write_to(&mut writer, geng::image::ImageFormat::Png)
-                .unwrap();
+            writer.write_all(png).unwrap();
             texture_data_end = writer.position();
+        } else {
+            texture_data_start = writer.position();
+            texture_data_end = texture_data_start;
         }
         writer.into_inner()
     };
@@ -110,17 +378,34 @@ pub fn save(ugli: &Ugli, sprite: &sprite_shape::ThickSprite<viewer::Vertex>) ->
         target: Some(Valid(json::buffer::Target::ArrayBuffer)),
     });
 
-    let image_buffer_view = root.push(json::buffer::View {
+    let index_data_view = root.push(json::buffer::View {
         buffer,
-        byte_length: USize64::from(texture_data_end - texture_data_start),
-        byte_offset: Some(texture_data_start.into()),
+        byte_length: USize64::from(index_data_end - index_data_start),
+        byte_offset: Some(USize64::from(index_data_start)),
         byte_stride: None,
+        extensions: Default::default(),
+        extras: Default::default(),
         name: None,
-        target: None,
-        extensions: None,
-        extras: default(),
+        target: Some(Valid(json::buffer::Target::ElementArrayBuffer)),
     });
 
+    let (image_buffer_view, image_uri) = match image_source {
+        ImageSource::Buffer(_) => (
+            Some(root.push(json::buffer::View {
+                buffer,
+                byte_length: USize64::from(texture_data_end - texture_data_start),
+                byte_offset: Some(texture_data_start.into()),
+                byte_stride: None,
+                name: None,
+                target: None,
+                extensions: None,
+                extras: default(),
+            })),
+            None,
+        ),
+        ImageSource::Uri(uri) => (None, Some(uri)),
+    };
+
     let positions = root.push(json::Accessor {
         buffer_view: Some(vertex_data_view),
         byte_offset: Some(USize64::from(std::mem::offset_of!(Vertex, position))),
@@ -138,8 +423,42 @@ pub fn save(ugli: &Ugli, sprite: &sprite_shape::ThickSprite<viewer::Vertex>) ->
         sparse: None,
     });
 
+    let normals = root.push(json::Accessor {
+        buffer_view: Some(vertex_data_view),
+        byte_offset: Some(USize64::from(std::mem::offset_of!(Vertex, normal))),
+        count: USize64::from(vertex_count),
+        component_type: Valid(json::accessor::GenericComponentType(
+            json::accessor::ComponentType::F32,
+        )),
+        extensions: Default::default(),
+        extras: Default::default(),
+        type_: Valid(json::accessor::Type::Vec3),
+        min: None,
+        max: None,
+        name: None,
+        normalized: false,
+        sparse: None,
+    });
+
+    let indices = root.push(json::Accessor {
+        buffer_view: Some(index_data_view),
+        byte_offset: Some(USize64(0)),
+        count: USize64::from(index_count),
+        component_type: Valid(json::accessor::GenericComponentType(
+            json::accessor::ComponentType::U32,
+        )),
+        extensions: Default::default(),
+        extras: Default::default(),
+        type_: Valid(json::accessor::Type::Scalar),
+        min: None,
+        max: None,
+        name: None,
+        normalized: false,
+        sparse: None,
+    });
+
     let image = root.push(json::Image {
-        buffer_view: Some(image_buffer_view),
+        buffer_view: image_buffer_view,
         mime_type: Some(json::image::MimeType(
             json::image::VALID_MIME_TYPES
                 .iter()
@@ -147,25 +466,46 @@ pub fn save(ugli: &Ugli, sprite: &sprite_shape::ThickSprite<viewer::Vertex>) ->
                 .unwrap()
                 .to_string(),
         )),
+        name: name.clone(),
+        uri: image_uri,
+        extensions: None,
+        extras: default(),
+    });
+
+    let sampler = root.push(json::texture::Sampler {
+        mag_filter: Some(Valid(match options.filter {
+            TextureFilter::Nearest => json::texture::MagFilter::Nearest,
+            TextureFilter::Linear => json::texture::MagFilter::Linear,
+        })),
+        min_filter: Some(Valid(match options.filter {
+            TextureFilter::Nearest => json::texture::MinFilter::Nearest,
+            TextureFilter::Linear => json::texture::MinFilter::Linear,
+        })),
         name: None,
-        uri: None,
+        wrap_s: Valid(wrapping_mode(options.clamp)),
+        wrap_t: Valid(wrapping_mode(options.clamp)),
         extensions: None,
         extras: default(),
     });
 
     let texture = root.push(json::Texture {
-        name: None,
-        sampler: None,
+        name: name.clone(),
+        sampler: Some(sampler),
         source: image,
         extensions: None,
         extras: default(),
     });
 
     let material = root.push(json::Material {
-        alpha_cutoff: None,
-        alpha_mode: default(),
-        double_sided: false,
-        name: None,
+        alpha_cutoff: (options.alpha_mode == AlphaMode::Mask)
+            .then_some(json::material::AlphaCutoff(options.alpha_cutoff)),
+        alpha_mode: Valid(match options.alpha_mode {
+            AlphaMode::Opaque => json::material::AlphaMode::Opaque,
+            AlphaMode::Mask => json::material::AlphaMode::Mask,
+            AlphaMode::Blend => json::material::AlphaMode::Blend,
+        }),
+        double_sided: options.double_sided,
+        name: name.clone(),
         pbr_metallic_roughness: json::material::PbrMetallicRoughness {
             base_color_factor: default(),
             base_color_texture: Some(json::texture::Info {
@@ -209,12 +549,13 @@ pub fn save(ugli: &Ugli, sprite: &sprite_shape::ThickSprite<viewer::Vertex>) ->
         attributes: {
             let mut map = std::collections::BTreeMap::new();
             map.insert(Valid(json::mesh::Semantic::Positions), positions);
+            map.insert(Valid(json::mesh::Semantic::Normals), normals);
             map.insert(Valid(json::mesh::Semantic::TexCoords(0)), uvs);
             map
         },
         extensions: Default::default(),
         extras: Default::default(),
-        indices: None,
+        indices: Some(indices),
         material: Some(material),
         mode: Valid(json::mesh::Mode::Triangles),
         targets: None,
@@ -223,38 +564,74 @@ pub fn save(ugli: &Ugli, sprite: &sprite_shape::ThickSprite<viewer::Vertex>) ->
     let mesh = root.push(json::Mesh {
         extensions: Default::default(),
         extras: Default::default(),
-        name: None,
+        name: name.clone(),
         primitives: vec![primitive],
         weights: None,
     });
 
     let node = root.push(json::Node {
         mesh: Some(mesh),
+        name: name.clone(),
         ..Default::default()
     });
 
-    root.push(json::Scene {
+    let scene = root.push(json::Scene {
         extensions: Default::default(),
         extras: Default::default(),
-        name: None,
+        name,
         nodes: vec![node],
     });
+    root.scene = Some(scene);
 
-    let json_string = json::serialize::to_string(&root).expect("Serialization error");
-    let mut json_offset = json_string.len();
-    align_to_multiple_of_four(&mut json_offset);
-    let all_data = to_padded_byte_vector(all_data);
-    let glb = gltf::binary::Glb {
-        header: gltf::binary::Header {
-            magic: *b"glTF",
-            version: 2,
-            // N.B., the size of binary glTF file is limited to range of `u32`.
-            length: (json_offset + all_data.len())
-                .try_into()
-                .expect("file size exceeds binary glTF limit"),
-        },
-        bin: Some(Cow::Owned(all_data)),
-        json: Cow::Owned(json_string.into_bytes()),
-    };
-    glb.to_vec().expect("glTF binary output error")
+    Ok((root, all_data))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn square_mesh() -> SpriteMesh {
+        let image = geng::image::RgbaImage::from_fn(32, 32, |x, y| {
+            let inside = (8..24).contains(&x) && (8..24).contains(&y);
+            geng::image::Rgba([255, 0, 0, if inside { u8::MAX } else { 0 }])
+        });
+        let options = Options {
+            blur_sigma: 1.0,
+            cell_size: 4,
+            ..default()
+        };
+        let mut mesh = SpriteMesh::new(&image, &options);
+        mesh.provenance.source_file = Some("square.png".to_owned());
+        mesh
+    }
+
+    #[test]
+    fn provenance_round_trip() {
+        let mesh = square_mesh();
+        for format in [Format::Glb, Format::Gltf, Format::GltfEmbedded] {
+            let files = export(&mesh, "square", &ExportOptions::default(), format).unwrap();
+            let provenance = load_provenance(&files[0].data).unwrap();
+            assert_eq!(provenance.options, mesh.provenance.options);
+            assert_eq!(provenance.source_file, mesh.provenance.source_file);
+            assert_eq!(provenance.source_hash, mesh.provenance.source_hash);
+            assert_eq!(provenance.generator, mesh.provenance.generator);
+        }
+    }
+
+    #[test]
+    fn empty_mesh_is_an_error() {
+        let image = geng::image::RgbaImage::new(8, 8);
+        let mesh = SpriteMesh::new(&image, &Options::default());
+        assert!(mesh.vertices.is_empty());
+        for format in [Format::Glb, Format::Gltf, Format::GltfEmbedded] {
+            assert!(export(&mesh, "empty", &ExportOptions::default(), format).is_err());
+        }
+    }
+
+    #[test]
+    fn provenance_missing() {
+        assert!(load_provenance(b"not a gltf").is_err());
+        let gltf = json::serialize::to_vec(&json::Root::default()).unwrap();
+        assert!(load_provenance(&gltf).is_err());
+    }
 }
@@ -0,0 +1,100 @@
+//! Undo and redo for the options edited in the viewer
+
+/// Snapshots of a value, a step is recorded whenever it differs from the last snapshot
+pub struct History<T> {
+    current: T,
+    undo: Vec<T>,
+    redo: Vec<T>,
+}
+
+impl<T: Clone + PartialEq> History<T> {
+    /// Oldest steps are forgotten beyond this
+    const LIMIT: usize = 100;
+
+    pub fn new(value: T) -> Self {
+        Self {
+            current: value,
+            undo: Vec::new(),
+            redo: Vec::new(),
+        }
+    }
+
+    /// Record `value` as a new step if it has changed
+    pub fn record(&mut self, value: &T) {
+        if *value == self.current {
+            return;
+        }
+        self.undo
+            .push(std::mem::replace(&mut self.current, value.clone()));
+        if self.undo.len() > Self::LIMIT {
+            self.undo.remove(0);
+        }
+        self.redo.clear();
+    }
+
+    /// Step back, returning the value to restore
+    pub fn undo(&mut self) -> Option<T> {
+        let previous = self.undo.pop()?;
+        self.redo
+            .push(std::mem::replace(&mut self.current, previous.clone()));
+        Some(previous)
+    }
+
+    /// Step forward again after [Self::undo]
+    pub fn redo(&mut self) -> Option<T> {
+        let next = self.redo.pop()?;
+        self.undo
+            .push(std::mem::replace(&mut self.current, next.clone()));
+        Some(next)
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn undo_redo() {
+        let mut history = History::new(0);
+        history.record(&0);
+        assert!(!history.can_undo());
+        history.record(&1);
+        history.record(&2);
+
+        assert_eq!(history.undo(), Some(1));
+        assert_eq!(history.undo(), Some(0));
+        assert_eq!(history.undo(), None);
+        assert!(history.can_redo());
+        assert_eq!(history.redo(), Some(1));
+
+        // A new step forgets what could be redone
+        history.record(&3);
+        assert!(!history.can_redo());
+        assert_eq!(history.redo(), None);
+        assert_eq!(history.undo(), Some(1));
+        assert_eq!(history.undo(), Some(0));
+    }
+
+    #[test]
+    fn oldest_steps_are_trimmed() {
+        let limit = History::<usize>::LIMIT;
+        let mut history = History::new(0);
+        for value in 1..=limit + 10 {
+            history.record(&value);
+        }
+        let mut undone = Vec::new();
+        while let Some(value) = history.undo() {
+            undone.push(value);
+        }
+        assert_eq!(undone.len(), limit);
+        assert_eq!(undone.last(), Some(&10));
+    }
+}
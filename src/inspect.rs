@@ -0,0 +1,38 @@
+//! Intermediate results of mesh generation, to see why a detail was lost
+
+use super::*;
+
+pub struct Inspection {
+    /// Source image blurred with `blur_sigma`, its alpha is the field the contour is extracted from
+    pub blurred: geng::image::RgbaImage,
+    /// Segments of the iso contour in uv coordinates
+    pub contour: Vec<[vec2<f32>; 2]>,
+    /// Size of a marching cell in uv coordinates, samples are taken at its corners
+    pub cell_size: vec2<f32>,
+    /// Number of marching cells covering the image
+    pub cells: vec2<usize>,
+}
+
+impl Inspection {
+    pub fn new(image: &geng::image::RgbaImage, options: &Options) -> Self {
+        let blurred = blur(image, options.blur_sigma);
+        let image_size = vec2(image.width(), image.height()).map(|x| x as f32);
+        let cell_size = vec2::splat(options.cell_size as f32) / image_size;
+        let contour = march(&blurred, options)
+            .iter()
+            .flat_map(|face| {
+                face.iter()
+                    .circular_tuple_windows()
+                    .filter(move |(a, b)| a.value == options.iso && b.value == options.iso)
+                    .map(move |(a, b)| [a.pos * cell_size, b.pos * cell_size])
+            })
+            .collect();
+        Self {
+            blurred,
+            contour,
+            cell_size,
+            cells: vec2(image.width(), image.height())
+                .map(|x| (x as usize + options.cell_size - 1) / options.cell_size),
+        }
+    }
+}
@@ -2,6 +2,16 @@ use std::collections::{BTreeMap, VecDeque};
 
 use geng::prelude::{itertools::Itertools, *};
 
+pub mod animation;
+pub mod glb;
+pub mod inspect;
+pub mod metrics;
+pub mod presets;
+pub mod raster;
+pub mod settings;
+pub mod stats;
+pub mod tune;
+
 pub struct ThickSprite<V: ugli::Vertex> {
     pub texture: ugli::Texture,
     pub mesh: ugli::VertexBuffer<V>,
@@ -9,21 +19,112 @@ pub struct ThickSprite<V: ugli::Vertex> {
 
 impl<V: ugli::Vertex + From<Vertex>> ThickSprite<V> {
     pub fn new(ugli: &Ugli, image: &geng::image::RgbaImage, options: &Options) -> Self {
-        let vertices = generate_mesh(image, options)
-            .into_iter()
-            .map(Into::into)
-            .collect();
-        let texture = ugli::Texture::from_image_image(ugli, image.clone());
-        let fixed_texture = fix_texture(ugli, &texture);
-        let mesh = ugli::VertexBuffer::new_static(ugli, vertices);
+        Self::from_mesh(ugli, &SpriteMesh::new(image, options))
+    }
+
+    pub fn from_mesh(ugli: &Ugli, mesh: &SpriteMesh) -> Self {
+        let vertices = mesh.vertices.iter().copied().map(Into::into).collect();
         Self {
-            texture: fixed_texture,
-            mesh,
+            texture: ugli::Texture::from_image_image(ugli, mesh.texture.clone()),
+            mesh: ugli::VertexBuffer::new_static(ugli, vertices),
         }
     }
 }
 
-#[derive(ugli::Vertex)]
+/// Generated mesh together with its texture, not tied to any graphics context
+#[derive(Clone)]
+pub struct SpriteMesh {
+    /// Triangle list
+    pub vertices: Vec<Vertex>,
+    /// Source image with the color of transparent texels filled from the nearest opaque one,
+    /// alpha is always kept as is and ignored by [glb::AlphaMode::Opaque] materials
+    pub texture: geng::image::RgbaImage,
+    pub provenance: Provenance,
+    pub stats: stats::MeshStats,
+}
+
+impl SpriteMesh {
+    /// `options` are expected to pass [Options::validate]
+    pub fn new(image: &geng::image::RgbaImage, options: &Options) -> Self {
+        Self::new_cancellable(image, options, || false).unwrap()
+    }
+
+    /// Like [SpriteMesh::new], but gives up between generation stages once `cancelled` returns true
+    pub fn new_cancellable(
+        image: &geng::image::RgbaImage,
+        options: &Options,
+        cancelled: impl Fn() -> bool,
+    ) -> Option<Self> {
+        let mut timings = stats::Timings::default();
+        let mut timer = Timer::new();
+        let blurred = blur(image, options.blur_sigma);
+        timings.blur = timer.tick().as_secs_f64();
+        if cancelled() {
+            return None;
+        }
+        let faces = march(&blurred, options);
+        timings.marching = timer.tick().as_secs_f64();
+        if cancelled() {
+            return None;
+        }
+        let vertices = build_mesh(&faces, vec2(image.width(), image.height()), options);
+        timings.mesh = timer.tick().as_secs_f64();
+        if cancelled() {
+            return None;
+        }
+        let texture = fix_texture(image);
+        timings.texture = timer.tick().as_secs_f64();
+        Some(Self {
+            stats: stats::MeshStats::new(
+                &faces,
+                &vertices,
+                options,
+                vec2(texture.width(), texture.height()),
+                timings,
+            ),
+            vertices,
+            texture,
+            provenance: Provenance::new(image, options),
+        })
+    }
+}
+
+/// Everything needed to regenerate a mesh, embedded into exported files
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Provenance {
+    pub generator: String,
+    pub options: Options,
+    /// File name of the source image, if known
+    pub source_file: Option<String>,
+    /// Hex encoded SHA-256 of the source image dimensions and RGBA pixels
+    pub source_hash: String,
+}
+
+impl Provenance {
+    pub fn new(image: &geng::image::RgbaImage, options: &Options) -> Self {
+        Self {
+            generator: format!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")),
+            options: *options,
+            source_file: None,
+            source_hash: image_hash(image),
+        }
+    }
+}
+
+fn image_hash(image: &geng::image::RgbaImage) -> String {
+    use sha2::Digest;
+    let mut hasher = sha2::Sha256::new();
+    hasher.update(image.width().to_le_bytes());
+    hasher.update(image.height().to_le_bytes());
+    hasher.update(image.as_raw());
+    hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+#[derive(ugli::Vertex, Debug, Copy, Clone)]
 pub struct Vertex {
     pub a_pos: vec3<f32>,
     pub a_uv: vec2<f32>,
@@ -91,10 +192,29 @@ fn marching_triangles(bb: Aabb2<i32>, f: impl Fn(vec2<i32>) -> f32, iso: f32) ->
     result
 }
 
-fn generate_mesh(image: &geng::image::RgbaImage, options: &Options) -> Vec<Vertex> {
-    let image_size = vec2(image.width(), image.height());
-    let blurred = geng::image::imageops::blur(image, options.blur_sigma);
-    let iso = options.iso;
+/// Gaussian blur, a `sigma` of zero leaves the image as is
+///
+/// `imageops::blur` treats zero as 1.0, which would soften pixel art by a pixel.
+pub fn blur(image: &geng::image::RgbaImage, sigma: f32) -> geng::image::RgbaImage {
+    if sigma == 0.0 {
+        image.clone()
+    } else {
+        geng::image::imageops::blur(image, sigma)
+    }
+}
+
+/// Generate the mesh from an image already blurred with `options.blur_sigma`
+fn generate_mesh_from_blurred(blurred: &geng::image::RgbaImage, options: &Options) -> Vec<Vertex> {
+    build_mesh(
+        &march(blurred, options),
+        vec2(blurred.width(), blurred.height()),
+        options,
+    )
+}
+
+/// Run marching triangles over the alpha of the blurred image, in cell coordinates
+fn march(blurred: &geng::image::RgbaImage, options: &Options) -> Vec<MarchFace> {
+    let image_size = vec2(blurred.width(), blurred.height());
 
     let cells = Aabb2::ZERO
         .extend_positive(
@@ -103,14 +223,14 @@ fn generate_mesh(image: &geng::image::RgbaImage, options: &Options) -> Vec<Verte
         )
         .extend_uniform(2);
 
-    let faces = marching_triangles(
+    marching_triangles(
         cells,
         |cell_pos| {
             let pos = cell_pos * options.cell_size as i32;
             if pos.x < 0
                 || pos.y < 0
-                || pos.x >= image.width() as i32
-                || pos.y >= image.height() as i32
+                || pos.x >= image_size.x as i32
+                || pos.y >= image_size.y as i32
             {
                 return 0.0;
             }
@@ -118,7 +238,12 @@ fn generate_mesh(image: &geng::image::RgbaImage, options: &Options) -> Vec<Verte
             blurred.get_pixel(x, image_size.y - 1 - y)[3] as f32 / u8::MAX as f32
         },
         options.iso,
-    );
+    )
+}
+
+/// Extrude the marched faces into front, back and side triangles
+fn build_mesh(faces: &[MarchFace], image_size: vec2<u32>, options: &Options) -> Vec<Vertex> {
+    let iso = options.iso;
 
     let normals: BTreeMap<[R32; 2], vec2<f32>> = faces
         .iter()
@@ -126,7 +251,7 @@ fn generate_mesh(image: &geng::image::RgbaImage, options: &Options) -> Vec<Verte
             face.iter()
                 .circular_tuple_windows()
                 .filter_map(|(a, b)| {
-                    let normal = (b.pos - a.pos).rotate_90().normalize_or_zero();
+                    let normal = -(b.pos - a.pos).rotate_90().normalize_or_zero();
                     (a.value == iso && b.value == iso).then_some([(a.pos, normal), (b.pos, normal)])
                 })
                 .flatten()
@@ -136,7 +261,12 @@ fn generate_mesh(image: &geng::image::RgbaImage, options: &Options) -> Vec<Verte
 
     let front = options
         .front_face
-        .then_some(faces.iter().flatten().map(|v| (v, 1.0)))
+        .then_some(
+            faces
+                .iter()
+                .flatten()
+                .map(|v| (v, 1.0, Some(vec3(0.0, 0.0, 1.0)))),
+        )
         .into_iter()
         .flatten();
     let back = options
@@ -145,7 +275,7 @@ fn generate_mesh(image: &geng::image::RgbaImage, options: &Options) -> Vec<Verte
             faces
                 .iter()
                 .flat_map(|face| face.iter().rev())
-                .map(|v| (v, -1.0)),
+                .map(|v| (v, -1.0, Some(vec3(0.0, 0.0, -1.0)))),
         )
         .into_iter()
         .flatten();
@@ -155,28 +285,32 @@ fn generate_mesh(image: &geng::image::RgbaImage, options: &Options) -> Vec<Verte
             .circular_tuple_windows()
             .filter_map(|(a, b)| {
                 (a.value == iso && b.value == iso).then_some([
-                    (a, 1.0),
-                    (a, -1.0),
-                    (b, -1.0),
-                    (a, 1.0),
-                    (b, -1.0),
-                    (b, 1.0),
+                    (a, 1.0, None),
+                    (a, -1.0, None),
+                    (b, -1.0, None),
+                    (a, 1.0, None),
+                    (b, -1.0, None),
+                    (b, 1.0, None),
                 ])
             })
             .flatten()
     });
 
     itertools::chain![front, back, side]
-        .map(|(v, z)| {
-            let normal = normals.get(&**v.pos.map(r32)).copied();
+        .map(|(v, z, face_normal)| {
+            let normal = face_normal.unwrap_or_else(|| {
+                normals
+                    .get(&**v.pos.map(r32))
+                    .copied()
+                    .unwrap_or(vec2::ZERO)
+                    .extend(0.0)
+            });
             let pixel_pos = v.pos.map(|x| x * options.cell_size as f32);
             let uv = pixel_pos / image_size.map(|x| x as f32);
             Vertex {
                 a_pos: uv.map(|x| x * 2.0 - 1.0).extend(z),
                 a_uv: uv,
-                a_normal: normal
-                    .map(|normal| normal.extend(0.0))
-                    .unwrap_or(vec3(0.0, 0.0, 1.0)),
+                a_normal: normal,
             }
         })
         .map(|mut v| {
@@ -192,12 +326,13 @@ fn generate_mesh(image: &geng::image::RgbaImage, options: &Options) -> Vec<Verte
         .collect()
 }
 
-#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
 pub enum ScalingMode {
     FixedHeight(f32),
 }
 
-#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Options {
     pub blur_sigma: f32,
     pub cell_size: usize,
@@ -222,30 +357,66 @@ impl Default for Options {
     }
 }
 
-fn fix_texture(ugli: &Ugli, texture: &ugli::Texture) -> ugli::Texture {
-    let framebuffer =
-        ugli::FramebufferRead::new_color(ugli, ugli::ColorAttachmentRead::Texture(texture));
-    let data = framebuffer.read_color();
+impl Options {
+    /// Reject values that generation can not handle, like a zero `cell_size` or NaN
+    pub fn validate(&self) -> anyhow::Result<()> {
+        anyhow::ensure!(self.cell_size >= 1, "cell_size must be at least 1");
+        anyhow::ensure!(
+            self.blur_sigma.is_finite() && self.blur_sigma >= 0.0,
+            "blur_sigma must be a non-negative number, got {}",
+            self.blur_sigma,
+        );
+        anyhow::ensure!(
+            (0.0..=1.0).contains(&self.iso),
+            "iso must be between 0 and 1, got {}",
+            self.iso,
+        );
+        anyhow::ensure!(
+            self.thickness.is_finite() && self.thickness >= 0.0,
+            "thickness must be a non-negative number, got {}",
+            self.thickness,
+        );
+        match self.scaling {
+            ScalingMode::FixedHeight(height) => anyhow::ensure!(
+                height.is_finite() && height > 0.0,
+                "height must be a positive number, got {height}",
+            ),
+        }
+        Ok(())
+    }
+}
+
+/// Fill the color of non-opaque texels with that of the nearest opaque one, keeping their alpha
+///
+/// Filtering and texels just outside of the contour then never pick up a stray color.
+fn fix_texture(image: &geng::image::RgbaImage) -> geng::image::RgbaImage {
+    let size = vec2(image.width(), image.height());
     let mut queue = VecDeque::new();
     let mut visited = HashSet::new();
-    for x in 0..texture.size().x {
-        for y in 0..texture.size().y {
-            if data.get(x, y).a == u8::MAX {
+    for x in 0..size.x {
+        for y in 0..size.y {
+            if image.get_pixel(x, y)[3] == u8::MAX {
                 queue.push_back((vec2(x, y), vec2(x, y)));
                 visited.insert(vec2(x, y));
             }
         }
     }
-    let mut new_data = vec![vec![Rgba::<f32>::BLUE; texture.size().y]; texture.size().x];
+    let mut result = geng::image::RgbaImage::from_pixel(
+        size.x,
+        size.y,
+        geng::image::Rgba([0, 0, u8::MAX, u8::MAX]),
+    );
     while let Some((v, nearest)) = queue.pop_front() {
-        new_data[v.x][v.y] = data.get(nearest.x, nearest.y).convert();
+        let [r, g, b, _] = image.get_pixel(nearest.x, nearest.y).0;
+        let alpha = image.get_pixel(v.x, v.y)[3];
+        result.put_pixel(v.x, v.y, geng::image::Rgba([r, g, b, alpha]));
         for d in [vec2(-1, 0), vec2(1, 0), vec2(0, 1), vec2(0, -1)] {
             let nv = v.map(|x| x as i32) + d;
             if nv.x < 0 || nv.y < 0 {
                 continue;
             }
-            let nv = nv.map(|x| x as usize);
-            if nv.x >= texture.size().x || nv.y >= texture.size().y {
+            let nv = nv.map(|x| x as u32);
+            if nv.x >= size.x || nv.y >= size.y {
                 continue;
             }
             if visited.contains(&nv) {
@@ -255,7 +426,7 @@ fn fix_texture(ugli: &Ugli, texture: &ugli::Texture) -> ugli::Texture {
             visited.insert(nv);
         }
     }
-    ugli::Texture::new_with(ugli, texture.size(), |pos| new_data[pos.x][pos.y])
+    result
 }
 
 impl<V: ugli::Vertex + From<Vertex> + 'static> geng::asset::Load for ThickSprite<V> {
@@ -3,23 +3,43 @@ use std::path::PathBuf;
 use geng::prelude::*;
 use geng_sprite_shape as sprite_shape;
 
+#[cfg(not(target_arch = "wasm32"))]
+mod batch;
+mod cli_options;
+mod convert;
+#[cfg(not(target_arch = "wasm32"))]
+mod file_watcher;
+mod generator;
+mod history;
+mod notifications;
+mod thumbnail;
+mod user_presets;
 mod viewer;
-mod glb;
+#[cfg(not(target_arch = "wasm32"))]
+mod watch;
+
+#[derive(clap::Subcommand)]
+enum Command {
+    /// Convert an image without opening a window
+    Convert(convert::ConvertArgs),
+    /// Convert many images in parallel, mirroring the directory structure
+    #[cfg(not(target_arch = "wasm32"))]
+    Batch(batch::BatchArgs),
+    /// Like batch, but keep regenerating whenever images or settings change
+    #[cfg(not(target_arch = "wasm32"))]
+    Watch(watch::WatchArgs),
+    /// Render a preview image of the mesh in software, no GPU needed
+    Thumbnail(thumbnail::ThumbnailArgs),
+}
 
 #[derive(clap::Parser)]
+#[clap(args_conflicts_with_subcommands = true)]
 struct CliArgs {
-    #[clap(long)]
-    cell_size: Option<usize>,
-    #[clap(long)]
-    iso: Option<f32>,
-    #[clap(long)]
-    thickness: Option<f32>,
-    #[clap(long)]
-    back_face: Option<bool>,
-    #[clap(long)]
-    front_face: Option<bool>,
-    #[clap(long)]
-    blur_sigma: Option<f32>,
+    #[clap(subcommand)]
+    command: Option<Command>,
+    #[clap(flatten)]
+    options: cli_options::OptionsArgs,
+    /// Image to open
     path: Option<PathBuf>,
     #[clap(flatten)]
     geng: geng::CliArgs,
@@ -27,6 +47,28 @@ struct CliArgs {
 
 fn main() {
     let cli_args: CliArgs = cli::parse();
+    if let Some(command) = cli_args.command {
+        let result = match command {
+            Command::Convert(args) => convert::run(args),
+            #[cfg(not(target_arch = "wasm32"))]
+            Command::Batch(args) => batch::run(args),
+            #[cfg(not(target_arch = "wasm32"))]
+            Command::Watch(args) => watch::run(args),
+            Command::Thumbnail(args) => thumbnail::run(args),
+        };
+        if let Err(e) = result {
+            eprintln!("error: {e:#}");
+            std::process::exit(1);
+        }
+        return;
+    }
+    let sprite_options = match cli_args.options.resolve() {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("error: {e:#}");
+            std::process::exit(1);
+        }
+    };
     Geng::run_with(
         &{
             let mut options = geng::ContextOptions::default();
@@ -35,26 +77,15 @@ fn main() {
             options
         },
         move |geng| async move {
-            let mut options = geng_sprite_shape::Options::default();
-            macro_rules! options {
-                    ($($op:ident,)*) => {
-                        $(if let Some($op) = cli_args.$op {
-                            options.$op = $op;
-                        })*
-                    }
-                }
-            options! {
-                cell_size,
-                iso,
-                thickness,
-                back_face,
-                front_face,
-                blur_sigma,
-            };
-            viewer::Viewer::new(&geng, cli_args.path.clone(), options)
-                .await
-                .run()
-                .await;
+            viewer::Viewer::new(
+                &geng,
+                cli_args.path.clone(),
+                cli_args.options,
+                sprite_options,
+            )
+            .await
+            .run()
+            .await;
         },
     );
 }
@@ -0,0 +1,319 @@
+//! Comparison of generated meshes against the source image
+
+use super::*;
+
+/// Alpha at which a source pixel starts counting as part of the silhouette
+pub const ALPHA_THRESHOLD: u8 = 128;
+
+/// Per pixel coverage, rows go top to bottom like in the image
+#[derive(Debug, Clone)]
+pub struct Mask {
+    pub size: vec2<u32>,
+    pub data: Vec<bool>,
+}
+
+impl Mask {
+    /// Thresholded alpha of the source image
+    pub fn from_alpha(image: &geng::image::RgbaImage) -> Self {
+        Self {
+            size: vec2(image.width(), image.height()),
+            data: image
+                .pixels()
+                .map(|pixel| pixel[3] >= ALPHA_THRESHOLD)
+                .collect(),
+        }
+    }
+
+    /// Projection of the mesh onto the image plane
+    ///
+    /// Pixels are covered if their center is inside any triangle.
+    /// Mesh generation samples the image at pixel centers, so that is where
+    /// integer mesh coordinates are mapped to.
+    pub fn from_mesh(vertices: &[Vertex], size: vec2<u32>) -> Self {
+        let mut mask = Self {
+            size,
+            data: vec![false; size.x as usize * size.y as usize],
+        };
+        let size_f = size.map(|x| x as f32);
+        for triangle in vertices.chunks_exact(3) {
+            let [a, b, c] = [0, 1, 2].map(|i| {
+                let uv = triangle[i].a_uv;
+                vec2(uv.x * size_f.x + 0.5, (1.0 - uv.y) * size_f.y - 0.5)
+            });
+            let area = vec2::skew(b - a, c - a);
+            if area.abs() < 1e-9 {
+                // Side walls project to lines
+                continue;
+            }
+            let min = vec2(a.x.min(b.x).min(c.x), a.y.min(b.y).min(c.y));
+            let max = vec2(a.x.max(b.x).max(c.x), a.y.max(b.y).max(c.y));
+            // Range of pixels whose centers may be inside
+            let first = |x: f32| (x - 0.5).ceil().max(0.0) as u32;
+            let last = |x: f32, size: u32| ((x - 0.5).floor() as i64).min(size as i64 - 1);
+            let (min, max) = (
+                vec2(first(min.x), first(min.y)),
+                vec2(last(max.x, size.x), last(max.y, size.y)),
+            );
+            if max.x < 0 || max.y < 0 {
+                continue;
+            }
+            for y in min.y..=max.y as u32 {
+                for x in min.x..=max.x as u32 {
+                    let p = vec2(x as f32 + 0.5, y as f32 + 0.5);
+                    let inside = [(a, b), (b, c), (c, a)]
+                        .into_iter()
+                        .all(|(from, to)| vec2::skew(to - from, p - from) * area.signum() >= 0.0);
+                    if inside {
+                        mask.set(vec2(x, y), true);
+                    }
+                }
+            }
+        }
+        mask
+    }
+
+    fn index(&self, pos: vec2<u32>) -> usize {
+        pos.y as usize * self.size.x as usize + pos.x as usize
+    }
+
+    pub fn get(&self, pos: vec2<u32>) -> bool {
+        self.data[self.index(pos)]
+    }
+
+    pub fn set(&mut self, pos: vec2<u32>, value: bool) {
+        let index = self.index(pos);
+        self.data[index] = value;
+    }
+
+    /// Number of covered pixels
+    pub fn area(&self) -> usize {
+        self.data.iter().filter(|&&covered| covered).count()
+    }
+}
+
+/// Silhouette fidelity of a mesh compared to its source image
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+pub struct Fidelity {
+    /// Intersection over union, `1` is a perfect match
+    pub iou: f32,
+    /// Largest distance in pixels from a pixel of either silhouette to the other one
+    pub hausdorff: f32,
+    /// Pixels of the source silhouette not covered by the mesh
+    pub lost_area: usize,
+    /// Pixels covered by the mesh outside of the source silhouette
+    pub added_area: usize,
+}
+
+impl Fidelity {
+    pub fn new(image: &geng::image::RgbaImage, vertices: &[Vertex]) -> Self {
+        let reference = Mask::from_alpha(image);
+        let mesh = Mask::from_mesh(vertices, reference.size);
+        Self::compare(&reference, &mesh)
+    }
+
+    pub fn compare(reference: &Mask, mesh: &Mask) -> Self {
+        let mut lost_area = 0;
+        let mut added_area = 0;
+        for (&a, &b) in reference.data.iter().zip(&mesh.data) {
+            lost_area += (a && !b) as usize;
+            added_area += (!a && b) as usize;
+        }
+        Self {
+            iou: iou(reference, mesh),
+            hausdorff: directed_hausdorff(reference, mesh).max(directed_hausdorff(mesh, reference)),
+            lost_area,
+            added_area,
+        }
+    }
+}
+
+fn iou(a: &Mask, b: &Mask) -> f32 {
+    let mut intersection = 0;
+    let mut union = 0;
+    for (&a, &b) in a.data.iter().zip(&b.data) {
+        intersection += (a && b) as usize;
+        union += (a || b) as usize;
+    }
+    if union == 0 {
+        return 1.0;
+    }
+    intersection as f32 / union as f32
+}
+
+/// Largest distance from a covered pixel of `from` to the nearest covered pixel of `to`
+fn directed_hausdorff(from: &Mask, to: &Mask) -> f32 {
+    let distances = squared_distance_transform(to);
+    from.data
+        .iter()
+        .zip(distances)
+        .filter(|&(&covered, _)| covered)
+        .map(|(_, distance)| distance)
+        .fold(0.0, f32::max)
+        .sqrt()
+}
+
+/// Squared euclidean distance from every pixel to the nearest covered one
+///
+/// Felzenszwalb & Huttenlocher, "Distance Transforms of Sampled Functions"
+fn squared_distance_transform(mask: &Mask) -> Vec<f32> {
+    let vec2(width, height) = mask.size.map(|x| x as usize);
+    let mut grid: Vec<f32> = mask
+        .data
+        .iter()
+        .map(|&covered| if covered { 0.0 } else { f32::INFINITY })
+        .collect();
+    let mut column = Vec::with_capacity(height);
+    for x in 0..width {
+        column.clear();
+        column.extend((0..height).map(|y| grid[y * width + x]));
+        for (y, value) in distance_transform_1d(&column).into_iter().enumerate() {
+            grid[y * width + x] = value;
+        }
+    }
+    for row in grid.chunks_mut(width.max(1)) {
+        let transformed = distance_transform_1d(row);
+        row.copy_from_slice(&transformed);
+    }
+    grid
+}
+
+fn distance_transform_1d(f: &[f32]) -> Vec<f32> {
+    // Lower envelope of the parabolas rooted at every finite sample:
+    // their locations and the boundaries where each one starts
+    let mut locations: Vec<usize> = Vec::with_capacity(f.len());
+    let mut boundaries: Vec<f32> = Vec::with_capacity(f.len());
+    for (q, &value) in f.iter().enumerate() {
+        if value == f32::INFINITY {
+            continue;
+        }
+        while let Some(&p) = locations.last() {
+            let s = ((value + (q * q) as f32) - (f[p] + (p * p) as f32)) / (2 * (q - p)) as f32;
+            if s > *boundaries.last().unwrap() {
+                boundaries.push(s);
+                locations.push(q);
+                break;
+            }
+            locations.pop();
+            boundaries.pop();
+        }
+        if locations.is_empty() {
+            locations.push(q);
+            boundaries.push(f32::NEG_INFINITY);
+        }
+    }
+    if locations.is_empty() {
+        return vec![f32::INFINITY; f.len()];
+    }
+    let mut current = 0;
+    (0..f.len())
+        .map(|q| {
+            while boundaries
+                .get(current + 1)
+                .is_some_and(|&boundary| boundary < q as f32)
+            {
+                current += 1;
+            }
+            let p = locations[current];
+            let d = q as f32 - p as f32;
+            d * d + f[p]
+        })
+        .collect()
+}
+
+/// `1 - IoU` of the mesh silhouette and the reference mask, `0` is a perfect match
+pub fn silhouette_error(reference: &Mask, vertices: &[Vertex]) -> f32 {
+    1.0 - iou(reference, &Mask::from_mesh(vertices, reference.size))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Mask from rows of `#` (covered) and `.`
+    fn mask(rows: &[&str]) -> Mask {
+        Mask {
+            size: vec2(rows[0].len() as u32, rows.len() as u32),
+            data: rows
+                .iter()
+                .flat_map(|row| row.chars().map(|c| c == '#'))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn iou_of_known_masks() {
+        let a = mask(&["##..", "##..", "....", "...."]);
+        let b = mask(&[".##.", ".##.", "....", "...."]);
+        let empty = mask(&["....", "....", "....", "...."]);
+        assert_eq!(iou(&a, &a), 1.0);
+        // 2 shared pixels out of 6 covered by either
+        assert_eq!(iou(&a, &b), 2.0 / 6.0);
+        assert_eq!(iou(&a, &empty), 0.0);
+        assert_eq!(iou(&empty, &empty), 1.0);
+    }
+
+    #[test]
+    fn distance_transform_matches_brute_force() {
+        // Deterministic pseudo random mask with a few covered pixels
+        let mut state = 12345u32;
+        let size = vec2(13, 9);
+        let data = (0..size.x * size.y)
+            .map(|_| {
+                state = state.wrapping_mul(1103515245).wrapping_add(12345);
+                (state >> 16) % 11 == 0
+            })
+            .collect();
+        let mask = Mask { size, data };
+        let covered: Vec<vec2<i32>> = (0..size.y)
+            .flat_map(|y| (0..size.x).map(move |x| vec2(x, y)))
+            .filter(|&pos| mask.get(pos))
+            .map(|pos| pos.map(|x| x as i32))
+            .collect();
+        assert!(!covered.is_empty());
+        let distances = squared_distance_transform(&mask);
+        for y in 0..size.y as i32 {
+            for x in 0..size.x as i32 {
+                let expected = covered
+                    .iter()
+                    .map(|&p| (p - vec2(x, y)).map(|d| d * d))
+                    .map(|d| (d.x + d.y) as f32)
+                    .fold(f32::INFINITY, f32::min);
+                assert_eq!(distances[(y * size.x as i32 + x) as usize], expected);
+            }
+        }
+    }
+
+    #[test]
+    fn distance_transform_of_empty_mask() {
+        let distances = squared_distance_transform(&mask(&["...", "..."]));
+        assert!(distances.iter().all(|&d| d == f32::INFINITY));
+    }
+
+    #[test]
+    fn fidelity_of_shifted_square() {
+        let reference = mask(&["......", ".##...", ".##...", "......"]);
+        let shifted = mask(&["......", "...##.", "...##.", "......"]);
+        let fidelity = Fidelity::compare(&reference, &shifted);
+        assert_eq!(fidelity.iou, 0.0);
+        assert_eq!(fidelity.hausdorff, 2.0);
+        assert_eq!(fidelity.lost_area, 4);
+        assert_eq!(fidelity.added_area, 4);
+    }
+
+    #[test]
+    fn mesh_of_square_matches_image() {
+        let image = geng::image::RgbaImage::from_fn(40, 30, |x, y| {
+            let inside = (10..30).contains(&x) && (5..25).contains(&y);
+            geng::image::Rgba([0, 0, 0, if inside { u8::MAX } else { 0 }])
+        });
+        let options = Options {
+            blur_sigma: 0.5,
+            cell_size: 2,
+            ..default()
+        };
+        let mesh = SpriteMesh::new(&image, &options);
+        let fidelity = Fidelity::new(&image, &mesh.vertices);
+        assert!(fidelity.iou > 0.9, "{fidelity:?}");
+        assert!(fidelity.hausdorff <= 2.0, "{fidelity:?}");
+    }
+}
@@ -0,0 +1,89 @@
+//! Errors and messages shown in a corner of the viewer, also logged to stderr
+
+use super::*;
+use geng_egui::egui;
+
+struct Notification {
+    message: String,
+    error: bool,
+    /// Seconds since the notification was last shown
+    age: f64,
+}
+
+#[derive(Default)]
+pub struct Notifications {
+    items: Vec<Notification>,
+}
+
+impl Notifications {
+    /// Seconds until a message disappears, errors stay until dismissed
+    const MESSAGE_DURATION: f64 = 5.0;
+
+    pub fn error(&mut self, message: impl Into<String>) {
+        let message = message.into();
+        log::error!("error: {message}");
+        self.push(message, true);
+    }
+
+    pub fn info(&mut self, message: impl Into<String>) {
+        let message = message.into();
+        log::info!("{message}");
+        self.push(message, false);
+    }
+
+    fn push(&mut self, message: String, error: bool) {
+        // A file that keeps failing to reload should not flood the list
+        if let Some(last) = self
+            .items
+            .last_mut()
+            .filter(|last| last.message == message && last.error == error)
+        {
+            last.age = 0.0;
+            return;
+        }
+        self.items.push(Notification {
+            message,
+            error,
+            age: 0.0,
+        });
+    }
+
+    pub fn update(&mut self, delta_time: f64) {
+        for item in &mut self.items {
+            item.age += delta_time;
+        }
+        self.items
+            .retain(|item| item.error || item.age < Self::MESSAGE_DURATION);
+    }
+
+    pub fn ui(&mut self, ctx: &egui::Context) {
+        if self.items.is_empty() {
+            return;
+        }
+        egui::Window::new("Notifications")
+            .anchor(egui::Align2::RIGHT_BOTTOM, [-8.0, -8.0])
+            .title_bar(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                let mut dismissed = None;
+                for (index, item) in self.items.iter().enumerate() {
+                    ui.horizontal(|ui| {
+                        if ui.small_button("x").clicked() {
+                            dismissed = Some(index);
+                        }
+                        if item.error {
+                            ui.colored_label(egui::Color32::LIGHT_RED, &item.message);
+                        } else {
+                            ui.label(&item.message);
+                        }
+                    });
+                }
+                if let Some(index) = dismissed {
+                    self.items.remove(index);
+                }
+                if self.items.len() > 1 && ui.button("Dismiss all").clicked() {
+                    self.items.clear();
+                }
+            });
+    }
+}
@@ -0,0 +1,92 @@
+//! Built-in [Options] for common kinds of sprites
+
+use super::*;
+
+pub struct Preset {
+    /// Identifier used on the command line
+    pub name: &'static str,
+    pub description: &'static str,
+    pub options: Options,
+}
+
+pub fn builtin() -> Vec<Preset> {
+    vec![
+        Preset {
+            name: "pixel-art",
+            description: "Follow every pixel of low resolution art",
+            options: Options {
+                blur_sigma: 0.0,
+                cell_size: 1,
+                iso: 0.5,
+                thickness: 0.02,
+                ..default()
+            },
+        },
+        Preset {
+            name: "hand-painted",
+            description: "Smooth outline that keeps brush stroke details",
+            options: Options {
+                blur_sigma: 4.0,
+                cell_size: 4,
+                iso: 0.5,
+                thickness: 0.02,
+                ..default()
+            },
+        },
+        Preset {
+            name: "coin",
+            description: "Round thick object with a simple outline",
+            options: Options {
+                blur_sigma: 10.0,
+                cell_size: 8,
+                iso: 0.5,
+                thickness: 0.1,
+                ..default()
+            },
+        },
+        Preset {
+            name: "foliage-card",
+            description: "Flat single sided card, export it double sided",
+            options: Options {
+                blur_sigma: 2.0,
+                cell_size: 6,
+                iso: 0.3,
+                thickness: 0.0,
+                back_face: false,
+                ..default()
+            },
+        },
+        Preset {
+            name: "thick-prop",
+            description: "Chunky prop with a coarse silhouette",
+            options: Options {
+                blur_sigma: 10.0,
+                cell_size: 10,
+                iso: 0.5,
+                thickness: 0.1,
+                ..default()
+            },
+        },
+    ]
+}
+
+pub fn find_builtin(name: &str) -> Option<Options> {
+    builtin()
+        .into_iter()
+        .find(|preset| preset.name == name)
+        .map(|preset| preset.options)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pixel_art_is_not_blurred() {
+        let options = find_builtin("pixel-art").unwrap();
+        let image = geng::image::RgbaImage::from_fn(5, 5, |x, y| {
+            geng::image::Rgba([255, 255, 255, if (x + y) % 2 == 0 { u8::MAX } else { 0 }])
+        });
+        assert_eq!(blur(&image, options.blur_sigma), image);
+    }
+}
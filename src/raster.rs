@@ -0,0 +1,339 @@
+//! Software rendering of a [SpriteMesh], for thumbnails where there is no GPU
+
+use super::*;
+
+/// Orbit camera that automatically frames the whole mesh, angles in degrees
+#[derive(Debug, Copy, Clone)]
+pub struct View {
+    /// Rotation around the vertical axis, zero looks at the front face
+    pub rotation: f32,
+    /// Positive looks from above
+    pub elevation: f32,
+    /// Vertical field of view
+    pub fov: f32,
+    pub orthographic: bool,
+}
+
+impl Default for View {
+    fn default() -> Self {
+        Self {
+            rotation: 30.0,
+            elevation: 20.0,
+            fov: 60.0,
+            orthographic: false,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct RenderOptions {
+    pub size: vec2<u32>,
+    pub view: View,
+    pub background: Rgba<f32>,
+    /// Direction towards the light in world space
+    pub light_direction: vec3<f32>,
+    pub ambient: f32,
+    /// Every pixel is the average of `supersampling * supersampling` samples
+    pub supersampling: u32,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        Self {
+            size: vec2(256, 256),
+            view: View::default(),
+            background: Rgba::TRANSPARENT_BLACK,
+            light_direction: vec3(0.5, 0.7, 1.0),
+            ambient: 0.4,
+            supersampling: 2,
+        }
+    }
+}
+
+/// Vertex after projection, `x` and `y` in pixels with y down
+#[derive(Copy, Clone)]
+struct Projected {
+    pos: vec2<f32>,
+    /// Distance along the view direction, used for depth testing
+    depth: f32,
+    /// Perspective divisor, attributes are interpolated divided by it
+    w: f32,
+}
+
+pub fn render(mesh: &SpriteMesh, options: &RenderOptions) -> geng::image::RgbaImage {
+    let samples = options.supersampling.max(1);
+    let size = options.size * samples;
+    let mut color = vec![Rgba::<f32>::TRANSPARENT_BLACK; (size.x * size.y) as usize];
+    let mut depth = vec![f32::INFINITY; color.len()];
+
+    if let Some([min, max]) = mesh.stats.bounding_box {
+        let view = &options.view;
+        let tan_half_fov = (view.fov.to_radians() / 2.0).tan();
+        let radius = (max - min).len() / 2.0;
+        let distance = radius * 1.1 / (view.fov.to_radians() / 2.0).sin();
+        let view_matrix = mat4::translate(vec3(0.0, 0.0, -distance))
+            * mat4::rotate_x(Angle::from_degrees(view.elevation))
+            * mat4::rotate_y(Angle::from_degrees(-view.rotation))
+            * mat4::translate(-(min + max) / 2.0);
+        let size_f = size.map(|x| x as f32);
+        let aspect = size_f.aspect();
+        let project = |pos: vec3<f32>| {
+            let p = view_matrix * pos.extend(1.0);
+            let w = if view.orthographic { distance } else { -p.z };
+            let ndc = vec2(p.x / (w * tan_half_fov * aspect), p.y / (w * tan_half_fov));
+            Projected {
+                pos: vec2(
+                    (ndc.x + 1.0) / 2.0 * size_f.x,
+                    (1.0 - ndc.y) / 2.0 * size_f.y,
+                ),
+                depth: -p.z,
+                w: if view.orthographic { 1.0 } else { w },
+            }
+        };
+        let light = options.light_direction.normalize_or_zero();
+
+        for triangle in mesh.vertices.chunks_exact(3) {
+            let projected = [0, 1, 2].map(|i| project(triangle[i].a_pos));
+            if projected.iter().any(|p| p.w <= 0.0) {
+                continue;
+            }
+            let [a, b, c] = projected.map(|p| p.pos);
+            // Image y points down, so front faces are clockwise here
+            let area = vec2::skew(b - a, c - a);
+            if area >= 0.0 {
+                continue;
+            }
+            let x_range = pixel_range(a.x.min(b.x).min(c.x), a.x.max(b.x).max(c.x), size.x);
+            let y_range = pixel_range(a.y.min(b.y).min(c.y), a.y.max(b.y).max(c.y), size.y);
+            for y in y_range {
+                for x in x_range.clone() {
+                    let p = vec2(x as f32 + 0.5, y as f32 + 0.5);
+                    let weights = [
+                        vec2::skew(c - b, p - b) / area,
+                        vec2::skew(a - c, p - c) / area,
+                        vec2::skew(b - a, p - a) / area,
+                    ];
+                    if weights.iter().any(|&weight| weight < 0.0) {
+                        continue;
+                    }
+                    // Perspective correct interpolation
+                    let perspective = [0, 1, 2].map(|i| weights[i] / projected[i].w);
+                    let total: f32 = perspective.iter().sum();
+                    let index = (y * size.x + x) as usize;
+                    let sample_depth = (0..3)
+                        .map(|i| perspective[i] * projected[i].depth)
+                        .sum::<f32>()
+                        / total;
+                    if sample_depth >= depth[index] {
+                        continue;
+                    }
+                    depth[index] = sample_depth;
+
+                    let interpolate = |f: &dyn Fn(&Vertex) -> vec3<f32>| {
+                        (0..3)
+                            .map(|i| f(&triangle[i]) * perspective[i])
+                            .fold(vec3::ZERO, |sum, value| sum + value)
+                            / total
+                    };
+                    let uv = interpolate(&|v| v.a_uv.extend(0.0)).xy();
+                    let normal = interpolate(&|v| v.a_normal);
+                    let shade = if normal == vec3::ZERO {
+                        1.0
+                    } else {
+                        options.ambient + vec3::dot(normal.normalize(), light).max(0.0)
+                    };
+                    let texel = sample(&mesh.texture, uv);
+                    // The mesh is opaque like with the default export, texture alpha is ignored
+                    color[index] = Rgba::new(
+                        (texel.r * shade).min(1.0),
+                        (texel.g * shade).min(1.0),
+                        (texel.b * shade).min(1.0),
+                        1.0,
+                    );
+                }
+            }
+        }
+    }
+
+    let background = options.background;
+    geng::image::RgbaImage::from_fn(options.size.x, options.size.y, |x, y| {
+        // Average premultiplied so the background does not bleed into the edges
+        let mut sum = vec4::<f32>::ZERO;
+        for sy in 0..samples {
+            for sx in 0..samples {
+                let index = ((y * samples + sy) * size.x + x * samples + sx) as usize;
+                let sample = if depth[index].is_finite() {
+                    color[index]
+                } else {
+                    background
+                };
+                sum += vec4(
+                    sample.r * sample.a,
+                    sample.g * sample.a,
+                    sample.b * sample.a,
+                    sample.a,
+                );
+            }
+        }
+        let sum = sum / (samples * samples) as f32;
+        let alpha = sum.w;
+        let unpremultiply = |value: f32| {
+            if alpha > 0.0 {
+                value / alpha
+            } else {
+                0.0
+            }
+        };
+        let to_byte = |value: f32| (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+        geng::image::Rgba([
+            to_byte(unpremultiply(sum.x)),
+            to_byte(unpremultiply(sum.y)),
+            to_byte(unpremultiply(sum.z)),
+            to_byte(alpha),
+        ])
+    })
+}
+
+/// Pixels whose centers may be covered by the span `min..max`
+fn pixel_range(min: f32, max: f32, size: u32) -> std::ops::Range<u32> {
+    let start = (min - 0.5).ceil().max(0.0) as u32;
+    let end = ((max - 0.5).floor() + 1.0).clamp(0.0, size as f32) as u32;
+    start..end.max(start)
+}
+
+/// Bilinear sample with clamping, `uv` has v pointing up like the mesh
+fn sample(texture: &geng::image::RgbaImage, uv: vec2<f32>) -> Rgba<f32> {
+    let size = vec2(texture.width(), texture.height());
+    let x = uv.x * size.x as f32 - 0.5;
+    let y = (1.0 - uv.y) * size.y as f32 - 0.5;
+    let texel = |x: i32, y: i32| {
+        let x = x.clamp(0, size.x as i32 - 1) as u32;
+        let y = y.clamp(0, size.y as i32 - 1) as u32;
+        let [r, g, b, a] = texture.get_pixel(x, y).0.map(|c| c as f32 / 255.0);
+        vec4(r, g, b, a)
+    };
+    let (x0, y0) = (x.floor(), y.floor());
+    let (tx, ty) = (x - x0, y - y0);
+    let (x0, y0) = (x0 as i32, y0 as i32);
+    let top = texel(x0, y0) * (1.0 - tx) + texel(x0 + 1, y0) * tx;
+    let bottom = texel(x0, y0 + 1) * (1.0 - tx) + texel(x0 + 1, y0 + 1) * tx;
+    let vec4(r, g, b, a) = top * (1.0 - ty) + bottom * ty;
+    Rgba::new(r, g, b, a)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COLOR: [u8; 4] = [200, 100, 50, 255];
+    const BACKGROUND: [u8; 4] = [0, 0, 255, 255];
+
+    fn square_mesh() -> SpriteMesh {
+        let image = geng::image::RgbaImage::from_fn(32, 32, |x, y| {
+            let inside = (8..24).contains(&x) && (8..24).contains(&y);
+            let [r, g, b, _] = COLOR;
+            geng::image::Rgba([r, g, b, if inside { u8::MAX } else { 0 }])
+        });
+        let options = Options {
+            blur_sigma: 1.0,
+            cell_size: 4,
+            ..default()
+        };
+        SpriteMesh::new(&image, &options)
+    }
+
+    /// Unlit, so every covered pixel has exactly the texture color
+    fn options(view: View, supersampling: u32) -> RenderOptions {
+        RenderOptions {
+            size: vec2(64, 64),
+            view,
+            background: Rgba::new(0.0, 0.0, 1.0, 1.0),
+            light_direction: vec3::ZERO,
+            ambient: 1.0,
+            supersampling,
+        }
+    }
+
+    fn front_view() -> View {
+        View {
+            rotation: 0.0,
+            elevation: 0.0,
+            ..default()
+        }
+    }
+
+    #[test]
+    fn front_view_covers_the_center() {
+        let image = render(&square_mesh(), &options(front_view(), 1));
+        assert_eq!(image.dimensions(), (64, 64));
+        assert_eq!(image.get_pixel(32, 32).0, COLOR);
+        for (x, y) in [(0, 0), (63, 0), (0, 63), (63, 63)] {
+            assert_eq!(image.get_pixel(x, y).0, BACKGROUND, "corner {x} {y}");
+        }
+    }
+
+    #[test]
+    fn front_face_wins() {
+        // Texture with a different color in each column, picked by uv
+        let colors = [[255, 0, 0, 255], [0, 255, 0, 255], [0, 0, 255, 255]];
+        let quad = |z: f32, column: usize, facing_camera: bool| {
+            let uv = vec2((column as f32 + 0.5) / 3.0, 0.5);
+            let mut corners = [
+                vec2(-1.0, -1.0),
+                vec2(1.0, -1.0),
+                vec2(1.0, 1.0),
+                vec2(-1.0, 1.0),
+            ];
+            if !facing_camera {
+                corners.reverse();
+            }
+            [0, 1, 2, 0, 2, 3].map(|i| Vertex {
+                a_pos: corners[i].extend(z),
+                a_uv: uv,
+                a_normal: vec3::ZERO,
+            })
+        };
+        let mut mesh = square_mesh();
+        mesh.texture =
+            geng::image::RgbaImage::from_fn(3, 1, |x, _| geng::image::Rgba(colors[x as usize]));
+        // Closer but facing away, then farther away but drawn last
+        mesh.vertices = [quad(0.1, 1, false), quad(0.0, 0, true), quad(-0.1, 2, true)]
+            .into_iter()
+            .flatten()
+            .collect();
+        mesh.stats.bounding_box = Some([vec3(-1.0, -1.0, -0.1), vec3(1.0, 1.0, 0.1)]);
+        let image = render(&mesh, &options(front_view(), 1));
+        assert_eq!(image.get_pixel(32, 32).0, colors[0]);
+
+        // From behind only the quad that was culled before faces the camera
+        let behind = View {
+            rotation: 180.0,
+            ..front_view()
+        };
+        let image = render(&mesh, &options(behind, 1));
+        assert_eq!(image.get_pixel(32, 32).0, colors[1]);
+    }
+
+    #[test]
+    fn supersampling_averages_edges() {
+        let mesh = square_mesh();
+        let is_mixed = |pixel: &geng::image::Rgba<u8>| pixel.0 != COLOR && pixel.0 != BACKGROUND;
+
+        let image = render(&mesh, &options(default(), 1));
+        assert!(!image.pixels().any(is_mixed));
+
+        let image = render(&mesh, &options(default(), 4));
+        let mixed: Vec<_> = image.pixels().filter(|&pixel| is_mixed(pixel)).collect();
+        assert!(!mixed.is_empty());
+        for pixel in mixed {
+            // Every mixed pixel lies between the texture and the background color
+            let [r, g, b, _] = pixel.0.map(|c| c as f32);
+            let coverage = r / COLOR[0] as f32;
+            assert!(0.0 < coverage && coverage < 1.0, "{pixel:?}");
+            assert!((g - coverage * COLOR[1] as f32).abs() <= 1.0, "{pixel:?}");
+            let expected_b = coverage * COLOR[2] as f32 + (1.0 - coverage) * 255.0;
+            assert!((b - expected_b).abs() <= 2.0, "{pixel:?}");
+            assert_eq!(pixel.0[3], u8::MAX);
+        }
+    }
+}
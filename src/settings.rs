@@ -0,0 +1,221 @@
+//! Versioned settings files in JSON or TOML
+//!
+//! Files contain the [Options] fields plus a `version` field.
+//! Missing fields take their default values, so files stay loadable when new options are added.
+
+use super::*;
+
+/// Version written by [to_string]
+pub const VERSION: u32 = 2;
+
+type Fields = serde_json::Map<String, serde_json::Value>;
+
+/// Migrations from version `i + 1` to `i + 2`
+const MIGRATIONS: [fn(&mut Fields); VERSION as usize - 1] = [v1_to_v2];
+
+/// Version 1 is the unversioned format written before the `version` field was introduced
+fn v1_to_v2(_fields: &mut Fields) {
+    // Same fields, only the version field was added
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Format {
+    Json,
+    Toml,
+}
+
+impl Format {
+    /// Guess the format from a file name, defaulting to JSON
+    pub fn from_file_name(name: &str) -> Self {
+        if name.to_lowercase().ends_with(".toml") {
+            Self::Toml
+        } else {
+            Self::Json
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Json => "json",
+            Self::Toml => "toml",
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Versioned<'a> {
+    version: u32,
+    #[serde(flatten)]
+    options: &'a Options,
+}
+
+pub fn to_string(options: &Options, format: Format) -> String {
+    let versioned = Versioned {
+        version: VERSION,
+        options,
+    };
+    match format {
+        Format::Json => serde_json::to_string_pretty(&versioned).unwrap(),
+        Format::Toml => toml::to_string_pretty(&versioned).unwrap(),
+    }
+}
+
+/// Parse settings of any known version, upgrading them to the current one
+pub fn parse(data: &[u8], format: Format) -> anyhow::Result<Options> {
+    apply(&Options::default(), data, format)
+}
+
+/// Like [parse], but fields missing from the (possibly partial) settings are taken from `base`
+pub fn apply(base: &Options, data: &[u8], format: Format) -> anyhow::Result<Options> {
+    let fields = parse_fields(data, format)?;
+    let serde_json::Value::Object(mut merged) = serde_json::to_value(base)? else {
+        unreachable!("options are serialized as an object");
+    };
+    merged.extend(fields);
+    let options: Options = serde_json::from_value(serde_json::Value::Object(merged))?;
+    options.validate()?;
+    Ok(options)
+}
+
+/// Fields present in the settings, migrated to the current version
+fn parse_fields(data: &[u8], format: Format) -> anyhow::Result<Fields> {
+    let value: serde_json::Value = match format {
+        Format::Json => serde_json::from_slice(data)?,
+        Format::Toml => toml::from_str(std::str::from_utf8(data)?)?,
+    };
+    let serde_json::Value::Object(mut fields) = value else {
+        anyhow::bail!("settings must be an object");
+    };
+    let version = match fields.remove("version") {
+        Some(version) => serde_json::from_value(version)?,
+        None => 1,
+    };
+    if version == 0 {
+        anyhow::bail!("invalid settings version 0");
+    }
+    if version > VERSION {
+        log::warn!(
+            "settings version {version} is newer than supported {VERSION}, \
+            unknown fields are ignored",
+        );
+    }
+    migrate(&mut fields, version, &MIGRATIONS);
+    Ok(fields)
+}
+
+/// Apply the migrations needed to upgrade from `version`, `migrations[0]` upgrades version 1
+fn migrate(fields: &mut Fields, version: u32, migrations: &[fn(&mut Fields)]) {
+    for migration in migrations.iter().skip(version as usize - 1) {
+        migration(fields);
+    }
+}
+
+pub fn load(path: &std::path::Path) -> anyhow::Result<Options> {
+    let data = std::fs::read(path)?;
+    parse(&data, Format::from_file_name(&path.to_string_lossy()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Written by the viewer's "Export settings.json" before settings were versioned
+    const V1_JSON: &str = r#"{
+  "blur_sigma": 4.0,
+  "cell_size": 6,
+  "iso": 0.3,
+  "thickness": 0.05,
+  "scaling": {
+    "FixedHeight": 2.0
+  },
+  "front_face": true,
+  "back_face": false
+}"#;
+
+    fn custom_options() -> Options {
+        Options {
+            blur_sigma: 4.0,
+            cell_size: 6,
+            iso: 0.3,
+            thickness: 0.05,
+            scaling: ScalingMode::FixedHeight(2.0),
+            front_face: true,
+            back_face: false,
+        }
+    }
+
+    #[test]
+    fn loads_v1() {
+        assert_eq!(
+            parse(V1_JSON.as_bytes(), Format::Json).unwrap(),
+            custom_options(),
+        );
+    }
+
+    #[test]
+    fn round_trip() {
+        for format in [Format::Json, Format::Toml] {
+            let data = to_string(&custom_options(), format);
+            assert!(data.contains("version"), "{data}");
+            assert_eq!(parse(data.as_bytes(), format).unwrap(), custom_options(),);
+        }
+    }
+
+    #[test]
+    fn missing_fields() {
+        let options = parse(b"version = 2\niso = 0.25\n", Format::Toml).unwrap();
+        assert_eq!(
+            options,
+            Options {
+                iso: 0.25,
+                ..Options::default()
+            },
+        );
+        let options = apply(&custom_options(), br#"{"cell_size": 3}"#, Format::Json).unwrap();
+        assert_eq!(
+            options,
+            Options {
+                cell_size: 3,
+                ..custom_options()
+            },
+        );
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(parse(b"[]", Format::Json).is_err());
+        assert!(parse(br#"{"version": 0}"#, Format::Json).is_err());
+        assert!(parse(br#"{"cell_size": "big"}"#, Format::Json).is_err());
+        assert!(parse(br#"{"cell_size": 0}"#, Format::Json).is_err());
+        assert!(parse(b"iso = 1.5", Format::Toml).is_err());
+        assert!(parse(b"blur_sigma = -1.0", Format::Toml).is_err());
+        assert!(parse(br#"{"scaling": {"FixedHeight": 0.0}}"#, Format::Json).is_err());
+    }
+
+    #[test]
+    fn migrations_run_from_file_version() {
+        fn rename(fields: &mut Fields) {
+            if let Some(value) = fields.remove("sigma") {
+                fields.insert("blur_sigma".to_owned(), value);
+            }
+        }
+        fn double(fields: &mut Fields) {
+            let value = fields["blur_sigma"].as_f64().unwrap();
+            fields.insert("blur_sigma".to_owned(), (value * 2.0).into());
+        }
+        let migrations: [fn(&mut Fields); 2] = [rename, double];
+        let fields = |json: &str| -> Fields { serde_json::from_str(json).unwrap() };
+
+        let mut v1 = fields(r#"{"sigma": 1.5}"#);
+        migrate(&mut v1, 1, &migrations);
+        assert_eq!(v1, fields(r#"{"blur_sigma": 3.0}"#));
+
+        let mut v2 = fields(r#"{"blur_sigma": 1.5}"#);
+        migrate(&mut v2, 2, &migrations);
+        assert_eq!(v2, fields(r#"{"blur_sigma": 3.0}"#));
+
+        let mut v3 = fields(r#"{"blur_sigma": 1.5}"#);
+        migrate(&mut v3, 3, &migrations);
+        assert_eq!(v3, fields(r#"{"blur_sigma": 1.5}"#));
+    }
+}
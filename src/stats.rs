@@ -0,0 +1,161 @@
+//! Statistics about a generated mesh, for display and diagnostics
+
+use super::*;
+
+/// Which part of the mesh a triangle belongs to
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Part {
+    Front,
+    Back,
+    Side,
+}
+
+#[derive(Debug, Default, Copy, Clone)]
+pub struct PartStats {
+    /// Unique vertices, as they end up in an indexed export
+    pub vertices: usize,
+    pub triangles: usize,
+}
+
+impl PartStats {
+    fn new(vertices: &[Vertex]) -> Self {
+        let unique: HashSet<[u32; 8]> = vertices
+            .iter()
+            .map(|vertex| {
+                let vec3(x, y, z) = vertex.a_pos;
+                let vec3(nx, ny, nz) = vertex.a_normal;
+                let vec2(u, v) = vertex.a_uv;
+                [x, y, z, nx, ny, nz, u, v].map(f32::to_bits)
+            })
+            .collect();
+        Self {
+            vertices: unique.len(),
+            triangles: vertices.len() / 3,
+        }
+    }
+}
+
+/// Time spent in each generation stage, in seconds
+#[derive(Debug, Default, Copy, Clone)]
+pub struct Timings {
+    pub blur: f64,
+    pub marching: f64,
+    pub mesh: f64,
+    pub texture: f64,
+}
+
+impl Timings {
+    pub fn total(&self) -> f64 {
+        self.blur + self.marching + self.mesh + self.texture
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MeshStats {
+    pub front: PartStats,
+    pub back: PartStats,
+    pub side: PartStats,
+    /// Separate pieces of the silhouette
+    pub islands: usize,
+    /// Holes inside of the islands
+    pub holes: usize,
+    /// Minimum and maximum corner in world units, `None` if the mesh is empty
+    pub bounding_box: Option<[vec3<f32>; 2]>,
+    pub texture_size: vec2<u32>,
+    pub timings: Timings,
+}
+
+impl MeshStats {
+    pub(crate) fn new(
+        faces: &[MarchFace],
+        vertices: &[Vertex],
+        options: &Options,
+        texture_size: vec2<u32>,
+        timings: Timings,
+    ) -> Self {
+        // Vertices are generated as front, back, then side
+        let face_vertices = faces.len() * 3;
+        let front_len = if options.front_face { face_vertices } else { 0 };
+        let back_len = if options.back_face { face_vertices } else { 0 };
+        let (front, rest) = vertices.split_at(front_len);
+        let (back, side) = rest.split_at(back_len);
+        let (islands, holes) = count_contours(faces, options.iso);
+        Self {
+            front: PartStats::new(front),
+            back: PartStats::new(back),
+            side: PartStats::new(side),
+            islands,
+            holes,
+            bounding_box: bounding_box(vertices),
+            texture_size,
+            timings,
+        }
+    }
+
+    pub fn vertices(&self) -> usize {
+        self.front.vertices + self.back.vertices + self.side.vertices
+    }
+
+    pub fn triangles(&self) -> usize {
+        self.front.triangles + self.back.triangles + self.side.triangles
+    }
+
+    /// Part of the vertex at `index` in [SpriteMesh::vertices]
+    pub fn part(&self, index: usize) -> Part {
+        let triangle = index / 3;
+        if triangle < self.front.triangles {
+            Part::Front
+        } else if triangle < self.front.triangles + self.back.triangles {
+            Part::Back
+        } else {
+            Part::Side
+        }
+    }
+}
+
+fn bounding_box(vertices: &[Vertex]) -> Option<[vec3<f32>; 2]> {
+    let first = vertices.first()?.a_pos;
+    Some(vertices.iter().fold([first, first], |[min, max], v| {
+        let p = v.a_pos;
+        [
+            vec3(min.x.min(p.x), min.y.min(p.y), min.z.min(p.z)),
+            vec3(max.x.max(p.x), max.y.max(p.y), max.z.max(p.z)),
+        ]
+    }))
+}
+
+/// Trace the iso contour into closed loops and count `(islands, holes)`
+///
+/// Faces wind counter-clockwise, so outer boundaries do too while holes go clockwise.
+fn count_contours(faces: &[MarchFace], iso: f32) -> (usize, usize) {
+    let key = |pos: vec2<f32>| **pos.map(r32);
+    let mut next: BTreeMap<[R32; 2], Vec<vec2<f32>>> = BTreeMap::new();
+    for face in faces {
+        for (a, b) in face.iter().circular_tuple_windows() {
+            if a.value == iso && b.value == iso {
+                next.entry(key(a.pos)).or_default().push(b.pos);
+            }
+        }
+    }
+    let mut islands = 0;
+    let mut holes = 0;
+    while let Some(&start) = next.keys().next() {
+        let start = vec2(start[0].raw(), start[1].raw());
+        let mut area = 0.0;
+        let mut current = start;
+        while let Some(to) = next.get_mut(&key(current)).and_then(|to| to.pop()) {
+            area += vec2::skew(current, to);
+            current = to;
+            if current == start {
+                break;
+            }
+        }
+        next.retain(|_, to| !to.is_empty());
+        if area > 0.0 {
+            islands += 1;
+        } else if area < 0.0 {
+            holes += 1;
+        }
+    }
+    (islands, holes)
+}
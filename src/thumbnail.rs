@@ -0,0 +1,119 @@
+use super::*;
+
+use anyhow::Context as _;
+use sprite_shape::{animation, raster};
+
+#[derive(clap::Args)]
+pub struct ThumbnailArgs {
+    /// Source image
+    input: PathBuf,
+    /// Output `.png`, or `.gif` / `.png` (APNG) with `--frames`,
+    /// defaults to the input with `.thumbnail.png` extension
+    #[clap(short, long)]
+    output: Option<PathBuf>,
+    /// Width and height in pixels
+    #[clap(long, default_value_t = 256)]
+    size: u32,
+    /// Camera rotation around the vertical axis in degrees, 0 looks at the front
+    #[clap(long, default_value_t = 30.0, allow_hyphen_values = true)]
+    rotation: f32,
+    /// Camera elevation in degrees, positive looks from above
+    #[clap(long, default_value_t = 20.0, allow_hyphen_values = true)]
+    elevation: f32,
+    #[clap(long)]
+    orthographic: bool,
+    /// Background color as `#rrggbb` or `#rrggbbaa`, transparent by default
+    #[clap(long, value_parser = parse_color)]
+    background: Option<Rgba<f32>>,
+    /// Render a full turntable with this many frames instead of a single image
+    #[clap(long)]
+    frames: Option<usize>,
+    /// Frame rate of the turntable
+    #[clap(long, default_value_t = 24.0)]
+    fps: f32,
+    #[clap(flatten)]
+    options: cli_options::OptionsArgs,
+}
+
+fn parse_color(s: &str) -> Result<Rgba<f32>, String> {
+    let hex = s.strip_prefix('#').unwrap_or(s);
+    let channel = |i: usize| {
+        hex.get(i * 2..i * 2 + 2)
+            .and_then(|channel| u8::from_str_radix(channel, 16).ok())
+            .map(|value| value as f32 / 255.0)
+    };
+    let channels: Option<Vec<f32>> = (0..hex.len() / 2).map(channel).collect();
+    match channels.as_deref() {
+        Some(&[r, g, b]) if hex.len() == 6 => Ok(Rgba::new(r, g, b, 1.0)),
+        Some(&[r, g, b, a]) if hex.len() == 8 => Ok(Rgba::new(r, g, b, a)),
+        _ => Err(format!(
+            "invalid color {s:?}, expected #rrggbb or #rrggbbaa"
+        )),
+    }
+}
+
+pub fn run(args: ThumbnailArgs) -> anyhow::Result<()> {
+    let options = args.options.resolve()?;
+    let image = convert::load_image(&args.input)?;
+    let mut mesh = sprite_shape::SpriteMesh::new(&image, &options);
+    mesh.provenance.source_file = args
+        .input
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned());
+    let output = args
+        .output
+        .unwrap_or_else(|| args.input.with_extension("thumbnail.png"));
+
+    let mut render_options = raster::RenderOptions {
+        size: vec2::splat(args.size),
+        view: raster::View {
+            rotation: args.rotation,
+            elevation: args.elevation,
+            orthographic: args.orthographic,
+            ..default()
+        },
+        ..default()
+    };
+    if let Some(background) = args.background {
+        render_options.background = background;
+    }
+
+    let data = match args.frames {
+        None => animation::encode_png(&raster::render(&mesh, &render_options), &mesh.provenance)?,
+        Some(frames) => {
+            let images: Vec<_> = (0..frames)
+                .map(|frame| {
+                    let mut render_options = render_options;
+                    render_options.view.rotation += 360.0 * frame as f32 / frames as f32;
+                    raster::render(&mesh, &render_options)
+                })
+                .collect();
+            let extension = output
+                .extension()
+                .map(|extension| extension.to_string_lossy().to_lowercase());
+            match extension.as_deref() {
+                Some("gif") => animation::encode_gif(&images, args.fps, &mesh.provenance)?,
+                Some("png") => animation::encode_apng(&images, args.fps, &mesh.provenance)?,
+                _ => anyhow::bail!("unsupported output {output:?}, expected .gif or .png"),
+            }
+        }
+    };
+    std::fs::write(&output, data).with_context(|| format!("failed to write {output:?}"))?;
+    println!("{} -> {}", args.input.display(), output.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn color_flag() {
+        assert_eq!(parse_color("#ff0000"), Ok(Rgba::new(1.0, 0.0, 0.0, 1.0)));
+        assert_eq!(parse_color("00ff0000"), Ok(Rgba::new(0.0, 1.0, 0.0, 0.0)));
+        assert!(parse_color("#fff").is_err());
+        assert!(parse_color("#ff00zz").is_err());
+        assert!(parse_color("#ff00000").is_err());
+        assert!(parse_color("#ff000000ff").is_err());
+    }
+}
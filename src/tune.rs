@@ -0,0 +1,111 @@
+//! Automatic choice of [Options] for a triangle budget or a silhouette error
+//!
+//! Searches over `cell_size` (which controls how much the outline is simplified)
+//! and `blur_sigma`, keeping the rest of the options as given.
+
+use super::*;
+
+const CELL_SIZES: &[usize] = &[1, 2, 3, 4, 5, 6, 8, 10, 12, 16, 20, 24, 32, 40, 50];
+const BLUR_SIGMAS: &[f32] = &[0.5, 1.0, 2.0, 4.0, 6.0, 10.0, 15.0, 20.0];
+
+/// Cell sizes producing more marching cells than this are skipped to keep the search fast
+const MAX_CELLS: u64 = 1 << 20;
+
+#[derive(Debug, Copy, Clone)]
+pub enum Target {
+    /// Lowest error with at most this many triangles
+    Triangles(usize),
+    /// Fewest triangles with at most this [metrics::silhouette_error]
+    MaxError(f32),
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct Tuned {
+    pub options: Options,
+    pub triangles: usize,
+    pub error: f32,
+    /// Whether the target was reached, otherwise the closest candidate is returned
+    pub reached: bool,
+}
+
+pub fn tune(image: &geng::image::RgbaImage, base: &Options, target: Target) -> Tuned {
+    tune_cancellable(image, base, target, || false).unwrap()
+}
+
+/// Like [tune], but gives up between candidates once `cancelled` returns true
+pub fn tune_cancellable(
+    image: &geng::image::RgbaImage,
+    base: &Options,
+    target: Target,
+    cancelled: impl Fn() -> bool,
+) -> Option<Tuned> {
+    let reference = metrics::Mask::from_alpha(image);
+    let cells = |cell_size: usize| {
+        let cell_size = cell_size as u32;
+        image.width().div_ceil(cell_size) as u64 * image.height().div_ceil(cell_size) as u64
+    };
+    let mut cell_sizes: Vec<usize> = CELL_SIZES
+        .iter()
+        .copied()
+        .filter(|&cell_size| cells(cell_size) <= MAX_CELLS)
+        .collect();
+    if cell_sizes.is_empty() {
+        cell_sizes.push(*CELL_SIZES.last().unwrap());
+    }
+
+    let mut candidates = Vec::new();
+    for &blur_sigma in BLUR_SIGMAS {
+        let blurred = blur(image, blur_sigma);
+        for &cell_size in &cell_sizes {
+            if cancelled() {
+                return None;
+            }
+            let options = Options {
+                blur_sigma,
+                cell_size,
+                ..*base
+            };
+            let vertices = generate_mesh_from_blurred(&blurred, &options);
+            candidates.push(Tuned {
+                options,
+                triangles: vertices.len() / 3,
+                error: metrics::silhouette_error(&reference, &vertices),
+                reached: false,
+            });
+        }
+    }
+
+    let by_error = |a: &&Tuned, b: &&Tuned| {
+        a.error
+            .total_cmp(&b.error)
+            .then(a.triangles.cmp(&b.triangles))
+    };
+    let by_triangles = |a: &&Tuned, b: &&Tuned| {
+        a.triangles
+            .cmp(&b.triangles)
+            .then(a.error.total_cmp(&b.error))
+    };
+    let (reached, fallback) = match target {
+        Target::Triangles(max) => (
+            candidates
+                .iter()
+                .filter(|candidate| candidate.triangles <= max)
+                .min_by(by_error),
+            candidates.iter().min_by(by_triangles),
+        ),
+        Target::MaxError(max) => (
+            candidates
+                .iter()
+                .filter(|candidate| candidate.error <= max)
+                .min_by(by_triangles),
+            candidates.iter().min_by(by_error),
+        ),
+    };
+    Some(match reached {
+        Some(tuned) => Tuned {
+            reached: true,
+            ..*tuned
+        },
+        None => *fallback.expect("there is always at least one candidate"),
+    })
+}
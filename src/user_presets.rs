@@ -0,0 +1,101 @@
+use super::*;
+
+use anyhow::Context as _;
+use sprite_shape::{presets, settings};
+
+/// Directory with user-defined presets, one settings file per preset
+#[cfg(not(target_arch = "wasm32"))]
+pub fn dir() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join(env!("CARGO_PKG_NAME")).join("presets"))
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn dir() -> Option<PathBuf> {
+    None
+}
+
+/// Names of user presets, sorted, without those shadowed by a built-in preset
+pub fn list() -> Vec<String> {
+    let Some(dir) = dir() else {
+        return Vec::new();
+    };
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut names: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == "json" || extension == "toml")
+        })
+        .filter_map(|path| Some(path.file_stem()?.to_string_lossy().into_owned()))
+        .filter(|name| presets::find_builtin(name).is_none())
+        .collect();
+    names.sort();
+    names.dedup();
+    names
+}
+
+fn validate_name(name: &str) -> anyhow::Result<()> {
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|c| c.is_alphanumeric() || matches!(c, '-' | '_' | ' '))
+    {
+        anyhow::bail!("invalid preset name {name:?}, use letters, digits, spaces, - and _");
+    }
+    Ok(())
+}
+
+/// Built-in preset with the given name, or a user preset if there is no such built-in
+pub fn find(name: &str) -> anyhow::Result<sprite_shape::Options> {
+    if let Some(options) = presets::find_builtin(name) {
+        return Ok(options);
+    }
+    validate_name(name)?;
+    let dir = dir().context("user presets are not supported on this platform")?;
+    for format in [settings::Format::Json, settings::Format::Toml] {
+        let path = dir.join(format!("{name}.{}", format.extension()));
+        if path.is_file() {
+            return cli_options::load_settings(&path);
+        }
+    }
+    let builtin: Vec<&str> = presets::builtin()
+        .into_iter()
+        .map(|preset| preset.name)
+        .collect();
+    let builtin = builtin.join(", ");
+    anyhow::bail!("unknown preset {name:?}, built-in presets are {builtin}")
+}
+
+/// Save as a user preset, built-in names are rejected since [find] would never load it
+pub fn save(name: &str, options: &sprite_shape::Options) -> anyhow::Result<PathBuf> {
+    validate_name(name)?;
+    if presets::find_builtin(name).is_some() {
+        anyhow::bail!("{name:?} is a built-in preset, choose another name");
+    }
+    let dir = dir().context("user presets are not supported on this platform")?;
+    std::fs::create_dir_all(&dir).with_context(|| format!("failed to create {dir:?}"))?;
+    let format = settings::Format::Json;
+    let path = dir.join(format!("{name}.{}", format.extension()));
+    std::fs::write(&path, settings::to_string(options, format))
+        .with_context(|| format!("failed to write {path:?}"))?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_invalid_names() {
+        let options = sprite_shape::Options::default();
+        for preset in presets::builtin() {
+            assert!(save(preset.name, &options).is_err());
+        }
+        assert!(save("", &options).is_err());
+        assert!(save("../escape", &options).is_err());
+        assert!(validate_name("my preset_2-b").is_ok());
+    }
+}
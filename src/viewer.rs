@@ -2,11 +2,15 @@ use geng::prelude::{futures::AsyncReadExt, itertools::Itertools};
 
 use super::*;
 use geng_egui::*;
+use sprite_shape::{animation, glb, inspect, metrics, presets, settings, stats, tune};
 
 #[derive(ugli::Vertex, Clone, Copy)]
 pub struct Vertex {
     pub a_pos: vec3<f32>,
     pub a_uv: vec2<f32>,
+    pub a_normal: vec3<f32>,
+    /// 0 for front, 1 for back and 2 for side triangles
+    pub a_part: f32,
 }
 
 impl From<geng_sprite_shape::Vertex> for Vertex {
@@ -14,33 +18,246 @@ impl From<geng_sprite_shape::Vertex> for Vertex {
         Self {
             a_pos: value.a_pos,
             a_uv: value.a_uv,
+            a_normal: value.a_normal,
+            a_part: 0.0,
         }
     }
 }
 
+/// Vertex of a helper line, only the position is used
+fn line_vertex(a_pos: vec3<f32>) -> Vertex {
+    Vertex {
+        a_pos,
+        a_uv: vec2::ZERO,
+        a_normal: vec3::ZERO,
+        a_part: 0.0,
+    }
+}
+
+/// Right half of a 1.8m tall human outline from the neck down to the crotch, x and y in meters
+const HUMAN_OUTLINE: [(f32, f32); 11] = [
+    (0.06, 1.52),
+    (0.23, 1.45),
+    (0.27, 1.10),
+    (0.26, 0.80),
+    (0.20, 0.80),
+    (0.19, 1.08),
+    (0.16, 1.00),
+    (0.18, 0.85),
+    (0.15, 0.0),
+    (0.04, 0.0),
+    (0.0, 0.80),
+];
+const HUMAN_HEAD_CENTER: f32 = 1.67;
+const HUMAN_HEAD_RADIUS: f32 = 0.13;
+
+/// Ground grid and human scale reference, placed under and next to the mesh
+struct SceneHelpers {
+    grid_minor: ugli::VertexBuffer<Vertex>,
+    grid_major: ugli::VertexBuffer<Vertex>,
+    human: ugli::VertexBuffer<Vertex>,
+}
+
+impl SceneHelpers {
+    fn new(geng: &Geng, config: &HelpersConfig, bounding_box: Option<[vec3<f32>; 2]>) -> Self {
+        let [min, max] = bounding_box.unwrap_or([vec3::ZERO; 2]);
+        let ground = min.y;
+
+        let lines = (config.grid_size / config.grid_step).round() as i32;
+        let extent = lines as f32 * config.grid_step;
+        let major_every = (config.grid_major_step / config.grid_step).round().max(1.0) as i32;
+        let mut grid_minor = Vec::new();
+        let mut grid_major = Vec::new();
+        for i in -lines..=lines {
+            let offset = i as f32 * config.grid_step;
+            let grid = if i % major_every == 0 {
+                &mut grid_major
+            } else {
+                &mut grid_minor
+            };
+            grid.extend([
+                vec3(offset, ground, -extent),
+                vec3(offset, ground, extent),
+                vec3(-extent, ground, offset),
+                vec3(extent, ground, offset),
+            ]);
+        }
+
+        let origin = vec3(max.x + config.human_offset, ground, 0.0);
+        let outline = HUMAN_OUTLINE
+            .iter()
+            .map(|&(x, y)| vec2(x, y))
+            .chain(HUMAN_OUTLINE.iter().rev().map(|&(x, y)| vec2(-x, y)));
+        let head = (0..24).map(|i| {
+            let angle = Angle::from_degrees(i as f32 * 15.0);
+            vec2(angle.cos(), angle.sin()) * HUMAN_HEAD_RADIUS + vec2(0.0, HUMAN_HEAD_CENTER)
+        });
+        let as_lines = |points: Vec<vec2<f32>>| {
+            points
+                .into_iter()
+                .circular_tuple_windows()
+                .flat_map(|(a, b)| [a, b])
+                .map(|p| origin + p.extend(0.0))
+                .collect::<Vec<_>>()
+        };
+        let human = itertools::chain![as_lines(outline.collect()), as_lines(head.collect())];
+
+        Self {
+            grid_minor: ugli::VertexBuffer::new_static(
+                geng.ugli(),
+                grid_minor.into_iter().map(line_vertex).collect(),
+            ),
+            grid_major: ugli::VertexBuffer::new_static(
+                geng.ugli(),
+                grid_major.into_iter().map(line_vertex).collect(),
+            ),
+            human: ugli::VertexBuffer::new_static(geng.ugli(), human.map(line_vertex).collect()),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum RenderMode {
+    Textured,
+    Normals,
+    UvChecker,
+    Parts,
+}
+
+impl RenderMode {
+    const ALL: [Self; 4] = [Self::Textured, Self::Normals, Self::UvChecker, Self::Parts];
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Textured => "textured",
+            Self::Normals => "normals",
+            Self::UvChecker => "uv checker",
+            Self::Parts => "front / back / side",
+        }
+    }
+
+    /// Value of `u_mode` in the debug shader
+    fn shader_mode(self) -> i32 {
+        match self {
+            Self::Textured => 0,
+            Self::Normals => 1,
+            Self::UvChecker => 2,
+            Self::Parts => 3,
+        }
+    }
+}
+
+/// Vertex of the flat 2D inspection view
+#[derive(ugli::Vertex, Clone, Copy)]
+struct FlatVertex {
+    a_pos: vec2<f32>,
+    a_uv: vec2<f32>,
+}
+
+/// Orbit camera around `target`, y is up so the sprite stands upright on the ground grid
 struct Camera {
+    target: vec3<f32>,
+    /// Rotation around the vertical axis, zero looks at the front face
     rotation: Angle,
+    /// Elevation, positive looks from above
     attack_angle: Angle,
     distance: f32,
     fov: Angle,
+    orthographic: bool,
+}
+
+impl Camera {
+    const NEAR: f32 = 0.01;
+    const FAR: f32 = 1000.0;
+
+    fn rotation_matrix(&self) -> mat4<f32> {
+        mat4::rotate_x(self.attack_angle) * mat4::rotate_y(-self.rotation)
+    }
+
+    /// Half of the visible height at the target
+    fn half_height(&self) -> f32 {
+        self.distance * (self.fov.as_radians() / 2.0).tan()
+    }
+
+    /// Move the target by `delta` in screen space, in pixels
+    fn pan(&mut self, delta: vec2<f32>, framebuffer_size: vec2<f32>) {
+        let world_per_pixel = 2.0 * self.half_height() / framebuffer_size.y;
+        let inverse = self.rotation_matrix().transpose();
+        let right = (inverse * vec4(1.0, 0.0, 0.0, 0.0)).xyz();
+        let up = (inverse * vec4(0.0, 1.0, 0.0, 0.0)).xyz();
+        self.target -= (right * delta.x + up * delta.y) * world_per_pixel;
+    }
+
+    /// Look at the whole box given by its corners
+    fn frame(&mut self, [min, max]: [vec3<f32>; 2]) {
+        self.target = (min + max) / 2.0;
+        let radius = (max - min).len() / 2.0;
+        self.distance = (radius * 1.1 / (self.fov.as_radians() / 2.0).sin()).max(Self::NEAR * 10.0);
+    }
 }
 
 impl AbstractCamera3d for Camera {
     fn view_matrix(&self) -> mat4<f32> {
         mat4::translate(vec3(0.0, 0.0, -self.distance))
-            * mat4::rotate_x(self.attack_angle)
-            * mat4::rotate_z(self.rotation)
+            * self.rotation_matrix()
+            * mat4::translate(-self.target)
     }
     fn projection_matrix(&self, framebuffer_size: vec2<f32>) -> mat4<f32> {
-        mat4::perspective(
-            self.fov.as_radians(),
-            framebuffer_size.aspect(),
-            0.1,
-            1000.0,
-        )
+        if self.orthographic {
+            let half_height = self.half_height();
+            // Maps view space depth -NEAR..-FAR to -1..1
+            mat4::scale(vec3(
+                1.0 / (half_height * framebuffer_size.aspect()),
+                1.0 / half_height,
+                -2.0 / (Self::FAR - Self::NEAR),
+            )) * mat4::translate(vec3(0.0, 0.0, (Self::FAR + Self::NEAR) / 2.0))
+        } else {
+            mat4::perspective(
+                self.fov.as_radians(),
+                framebuffer_size.aspect(),
+                Self::NEAR,
+                Self::FAR,
+            )
+        }
     }
 }
 
+/// Camera orientations selectable with the number keys
+#[derive(Debug, Copy, Clone)]
+enum ViewPreset {
+    Front,
+    Back,
+    Left,
+    Right,
+    Top,
+}
+
+impl ViewPreset {
+    const ALL: [Self; 5] = [Self::Front, Self::Back, Self::Left, Self::Right, Self::Top];
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Front => "front",
+            Self::Back => "back",
+            Self::Left => "left",
+            Self::Right => "right",
+            Self::Top => "top",
+        }
+    }
+
+    /// `(rotation, attack_angle)` in degrees
+    fn angles(self) -> (f32, f32) {
+        match self {
+            Self::Front => (0.0, 0.0),
+            Self::Back => (180.0, 0.0),
+            Self::Left => (-90.0, 0.0),
+            Self::Right => (90.0, 0.0),
+            Self::Top => (0.0, 90.0),
+        }
+    }
+}
+
+/// Angles in degrees, see [Camera] for their meaning
 #[derive(Deserialize)]
 struct CameraConfig {
     fov: f32,
@@ -49,18 +266,93 @@ struct CameraConfig {
     attack_angle: f32,
 }
 
+#[derive(Deserialize)]
+struct HelpersConfig {
+    /// Half size of the ground grid in meters
+    grid_size: f32,
+    grid_step: f32,
+    /// Spacing of the brighter grid lines
+    grid_major_step: f32,
+    grid_color: Rgba<f32>,
+    grid_major_color: Rgba<f32>,
+    axes_size: f32,
+    human_color: Rgba<f32>,
+    /// Distance between the mesh and the human reference
+    human_offset: f32,
+}
+
+/// Directional light, angles in degrees
+#[derive(Deserialize, Clone)]
+struct LightConfig {
+    enabled: bool,
+    /// Rotation around the z axis
+    azimuth: f32,
+    /// Angle above the sprite plane, 90 is straight in front of it
+    elevation: f32,
+    color: Rgba<f32>,
+    ambient: f32,
+    /// Strength of the rim light
+    rim: f32,
+}
+
+impl LightConfig {
+    /// Direction towards the light
+    fn direction(&self) -> vec3<f32> {
+        let azimuth = Angle::from_degrees(self.azimuth);
+        let elevation = Angle::from_degrees(self.elevation);
+        vec3(
+            elevation.cos() * azimuth.cos(),
+            elevation.cos() * azimuth.sin(),
+            elevation.sin(),
+        )
+    }
+}
+
 #[derive(Deserialize)]
 struct Config {
+    /// Seconds to wait after a watched file changes before reloading it
+    file_reload_debounce: f64,
     background_color: Rgba<f32>,
     wireframe_color: Rgba<f32>,
+    normal_color: Rgba<f32>,
+    /// Length of the normal lines in world units
+    normal_length: f32,
+    /// Number of checkerboard squares across the texture in the uv checker render mode
+    checker_size: f32,
+    contour_color: Rgba<f32>,
+    marching_grid_color: Rgba<f32>,
     sensitivity: f32,
+    /// Zoom factor per unit of mouse wheel movement, exponential
+    zoom_sensitivity: f32,
     camera: CameraConfig,
+    light: LightConfig,
+    helpers: HelpersConfig,
+}
+
+/// What to draw on top of the source image in the inspection view
+struct Overlays {
+    /// Alpha of the blurred image
+    field: bool,
+    contour: bool,
+    grid: bool,
+    /// Show the texture with transparent texels filled in instead of the source image
+    texture: bool,
 }
 
 struct ViewerOptions {
     background_color: Rgba<f32>,
     wireframe: bool,
     culling: bool,
+    render_mode: RenderMode,
+    normal_lines: bool,
+    ground_grid: bool,
+    axes: bool,
+    human_reference: bool,
+    /// Show the source image flat with generation overlays instead of the mesh
+    inspect: bool,
+    overlays: Overlays,
+    light: LightConfig,
+    rim_light: bool,
 }
 
 impl ViewerOptions {
@@ -69,6 +361,38 @@ impl ViewerOptions {
             background_color: config.background_color,
             wireframe: false,
             culling: true,
+            render_mode: RenderMode::Textured,
+            normal_lines: false,
+            ground_grid: true,
+            axes: true,
+            human_reference: false,
+            inspect: false,
+            light: config.light.clone(),
+            rim_light: config.light.rim > 0.0,
+            overlays: Overlays {
+                field: false,
+                contour: true,
+                grid: false,
+                texture: false,
+            },
+        }
+    }
+}
+
+struct TuneState {
+    by_error: bool,
+    triangles: usize,
+    max_error: f32,
+    result: Option<tune::Tuned>,
+}
+
+impl Default for TuneState {
+    fn default() -> Self {
+        Self {
+            by_error: false,
+            triangles: 2000,
+            max_error: 0.02,
+            result: None,
         }
     }
 }
@@ -77,22 +401,44 @@ impl ViewerOptions {
 struct Shaders {
     program: ugli::Program,
     wireframe: ugli::Program,
+    debug: ugli::Program,
+    flat: ugli::Program,
 }
 
 struct Sprite {
+    helpers: SceneHelpers,
     wireframe_geometry: ugli::VertexBuffer<Vertex>,
+    normal_lines: ugli::VertexBuffer<Vertex>,
+    mesh: sprite_shape::SpriteMesh,
     shape: sprite_shape::ThickSprite<Vertex>,
+    fidelity: metrics::Fidelity,
 }
 
 impl Sprite {
-    fn new(geng: &Geng, image: &geng::image::RgbaImage, options: &sprite_shape::Options) -> Self {
-        let shape: sprite_shape::ThickSprite<Vertex> =
-            sprite_shape::ThickSprite::new(geng.ugli(), image, options);
+    fn new(
+        geng: &Geng,
+        mesh: sprite_shape::SpriteMesh,
+        fidelity: metrics::Fidelity,
+        config: &Config,
+    ) -> Self {
+        let vertices: Vec<Vertex> = mesh
+            .vertices
+            .iter()
+            .enumerate()
+            .map(|(index, &vertex)| Vertex {
+                a_part: match mesh.stats.part(index) {
+                    stats::Part::Front => 0.0,
+                    stats::Part::Back => 1.0,
+                    stats::Part::Side => 2.0,
+                },
+                ..vertex.into()
+            })
+            .collect();
         Self {
+            helpers: SceneHelpers::new(geng, &config.helpers, mesh.stats.bounding_box),
             wireframe_geometry: ugli::VertexBuffer::new_static(
                 geng.ugli(),
-                shape
-                    .mesh
+                vertices
                     .chunks(3)
                     .flat_map(|face| {
                         face.iter()
@@ -102,93 +448,362 @@ impl Sprite {
                     .cloned()
                     .collect(),
             ),
-            shape,
+            normal_lines: ugli::VertexBuffer::new_static(
+                geng.ugli(),
+                vertices
+                    .iter()
+                    .flat_map(|&vertex| {
+                        [
+                            vertex,
+                            Vertex {
+                                a_pos: vertex.a_pos + vertex.a_normal * config.normal_length,
+                                ..vertex
+                            },
+                        ]
+                    })
+                    .collect(),
+            ),
+            shape: sprite_shape::ThickSprite {
+                texture: ugli::Texture::from_image_image(geng.ugli(), mesh.texture.clone()),
+                mesh: ugli::VertexBuffer::new_static(geng.ugli(), vertices),
+            },
+            mesh,
+            fidelity,
         }
     }
 }
 
+/// GPU resources of the inspection view, rebuilt whenever the sprite is
+struct InspectionView {
+    quad: ugli::VertexBuffer<FlatVertex>,
+    source: ugli::Texture,
+    /// Mesh texture with alpha dropped, to show how far colors were dilated
+    dilated: ugli::Texture,
+    field: ugli::Texture,
+    contour: ugli::VertexBuffer<FlatVertex>,
+    grid: ugli::VertexBuffer<FlatVertex>,
+    /// Image width divided by height, the image is drawn `aspect` wide and 1 high
+    aspect: f32,
+}
+
+impl InspectionView {
+    fn new(
+        geng: &Geng,
+        image: &geng::image::RgbaImage,
+        texture: &geng::image::RgbaImage,
+        inspection: inspect::Inspection,
+    ) -> Self {
+        let aspect = image.width() as f32 / image.height() as f32;
+        let vertex = |uv: vec2<f32>| FlatVertex {
+            a_pos: vec2(uv.x * aspect, uv.y),
+            a_uv: uv,
+        };
+        let mut field = inspection.blurred;
+        for pixel in field.pixels_mut() {
+            let alpha = pixel[3];
+            *pixel = geng::image::Rgba([alpha, alpha, alpha, u8::MAX]);
+        }
+        let mut dilated = texture.clone();
+        for pixel in dilated.pixels_mut() {
+            pixel[3] = u8::MAX;
+        }
+        let extent = inspection.cells.map(|x| x as f32) * inspection.cell_size;
+        let vertical = (0..=inspection.cells.x).flat_map(|x| {
+            let x = x as f32 * inspection.cell_size.x;
+            [vec2(x, 0.0), vec2(x, extent.y)]
+        });
+        let horizontal = (0..=inspection.cells.y).flat_map(|y| {
+            let y = y as f32 * inspection.cell_size.y;
+            [vec2(0.0, y), vec2(extent.x, y)]
+        });
+        let quad = [
+            vec2(0.0, 0.0),
+            vec2(1.0, 0.0),
+            vec2(1.0, 1.0),
+            vec2(0.0, 1.0),
+        ];
+        Self {
+            quad: ugli::VertexBuffer::new_static(
+                geng.ugli(),
+                quad.into_iter().map(vertex).collect(),
+            ),
+            source: ugli::Texture::from_image_image(geng.ugli(), image.clone()),
+            dilated: ugli::Texture::from_image_image(geng.ugli(), dilated),
+            field: ugli::Texture::from_image_image(geng.ugli(), field),
+            contour: ugli::VertexBuffer::new_static(
+                geng.ugli(),
+                inspection
+                    .contour
+                    .iter()
+                    .flatten()
+                    .copied()
+                    .map(vertex)
+                    .collect(),
+            ),
+            grid: ugli::VertexBuffer::new_static(
+                geng.ugli(),
+                vertical.chain(horizontal).map(vertex).collect(),
+            ),
+            aspect,
+        }
+    }
+
+    /// Fit the image into the framebuffer with some margin
+    fn matrix(&self, framebuffer_size: vec2<f32>) -> mat3<f32> {
+        let size = vec2(self.aspect, 1.0) * 1.1;
+        let scale = (framebuffer_size.x / size.x).min(framebuffer_size.y / size.y);
+        mat3::scale(vec2(
+            2.0 * scale / framebuffer_size.x,
+            2.0 * scale / framebuffer_size.y,
+        )) * mat3::translate(-vec2(self.aspect, 1.0) / 2.0)
+    }
+}
+
+/// Read the whole file picked in a file dialog
+async fn read_selected_file(file: file_dialog::SelectedFile) -> anyhow::Result<Vec<u8>> {
+    let mut reader = file.reader()?;
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data).await?;
+    Ok(data)
+}
+
+fn is_gltf_file(name: &str) -> bool {
+    name.ends_with(".glb") || name.ends_with(".gltf")
+}
+
+#[derive(Debug, Copy, Clone)]
+enum RenderRequest {
+    Screenshot,
+    Turntable,
+}
+
+/// Screenshot and turntable settings
+struct RenderState {
+    transparent: bool,
+    frames: usize,
+    fps: f32,
+    format: animation::Format,
+    /// Rendered after the next frame is drawn, outside of the UI pass
+    pending: Option<RenderRequest>,
+}
+
+impl Default for RenderState {
+    fn default() -> Self {
+        Self {
+            transparent: false,
+            frames: 36,
+            fps: 24.0,
+            format: animation::Format::Gif,
+            pending: None,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+enum DragKind {
+    Rotate,
+    Pan,
+}
+
+#[derive(Debug, Copy, Clone)]
+struct Drag {
+    kind: DragKind,
+    last: vec2<f64>,
+}
+
 pub struct Viewer {
     geng: Geng,
-    shaders: Shaders,
+    /// `None` if the shaders failed to load, then only the UI is drawn
+    shaders: Option<Shaders>,
     viewer_options: ViewerOptions,
     white_texture: ugli::Texture,
+    /// X, Y and Z axis of the corner gizmo
+    axes: [ugli::VertexBuffer<Vertex>; 3],
     config: Config,
     framebuffer_size: vec2<f32>,
     camera: Camera,
     sprite_options: sprite_shape::Options,
-    image: Option<geng::image::RgbaImage>,
+    /// Edits of `sprite_options`, slider drags are recorded once released
+    history: history::History<sprite_shape::Options>,
+    export_options: glb::ExportOptions,
+    export_format: glb::Format,
+    image: Option<Arc<geng::image::RgbaImage>>,
+    /// File name of the loaded image
+    image_file: Option<String>,
+    /// Path the image was loaded from, if it should be reloaded on change
+    image_path: Option<PathBuf>,
+    /// Names of presets in the user config directory
+    user_presets: Vec<String>,
+    /// Name to save the current options as a user preset with
+    preset_name: String,
+    tune: TuneState,
+    render: RenderState,
+    /// Command line options, reapplied when the settings file is reloaded
+    options_args: cli_options::OptionsArgs,
+    #[cfg(not(target_arch = "wasm32"))]
+    file_watcher: Option<file_watcher::FileWatcher>,
+    generator: generator::Generator,
+    tuner: generator::Worker<generator::TuneRequest, tune::Tuned>,
     sprite: Option<Sprite>,
-    drag: Option<vec2<f64>>,
+    inspection: Option<InspectionView>,
+    /// The latest generation failed, it is not retried until the image or options change
+    generation_failed: bool,
+    drag: Option<Drag>,
+    /// Positions of the fingers on the screen, by touch id
+    touches: HashMap<u64, vec2<f64>>,
     should_quit: bool,
     egui: EguiGeng,
     should_reload: bool,
     file_selection: Rc<RefCell<Option<file_dialog::SelectedFile>>>,
     settings_file_selection: Rc<RefCell<Option<file_dialog::SelectedFile>>>,
+    notifications: notifications::Notifications,
 }
 
 impl Viewer {
     pub async fn new(
         geng: &Geng,
         path: Option<PathBuf>,
+        options_args: cli_options::OptionsArgs,
         sprite_options: sprite_shape::Options,
     ) -> Self {
-        let config: Config = file::load_detect(run_dir().join("assets").join("config.toml"))
-            .await
-            .unwrap();
-        let shaders: Shaders = geng
-            .asset_manager()
-            .load(run_dir().join("assets").join("shaders"))
-            .await
-            .unwrap();
-        let image = match path {
-            Some(path) => Some(geng.asset_manager().load(path).await.unwrap()),
+        let mut notifications = notifications::Notifications::default();
+        let config_path = run_dir().join("assets").join("config.toml");
+        let config: Config = match file::load_detect(&config_path).await {
+            Ok(config) => config,
+            Err(e) => {
+                notifications.error(format!(
+                    "failed to load {config_path:?}, using defaults: {e:#}"
+                ));
+                toml::from_str(include_str!("../assets/config.toml"))
+                    .expect("built in config is valid")
+            }
+        };
+        let shaders_path = run_dir().join("assets").join("shaders");
+        let shaders: Option<Shaders> = match geng.asset_manager().load(&shaders_path).await {
+            Ok(shaders) => Some(shaders),
+            Err(e) => {
+                notifications.error(format!("failed to load {shaders_path:?}: {e:#}"));
+                None
+            }
+        };
+        let image = match &path {
+            Some(path) => match geng.asset_manager().load(path).await {
+                Ok(image) => Some(Arc::new(image)),
+                Err(e) => {
+                    notifications.error(format!("failed to load {path:?}: {e:#}"));
+                    None
+                }
+            },
             None => None,
         };
+        // Exports and provenance are only named after an image that actually loaded
+        let image_file = path
+            .as_ref()
+            .filter(|_| image.is_some())
+            .and_then(|path| path.file_name())
+            .map(|name| name.to_string_lossy().into_owned());
+        #[cfg(not(target_arch = "wasm32"))]
+        let file_watcher = {
+            let files: Vec<&PathBuf> = path.iter().chain(&options_args.settings).collect();
+            let watch = || -> anyhow::Result<file_watcher::FileWatcher> {
+                let mut watcher = file_watcher::FileWatcher::new(
+                    std::time::Duration::from_secs_f64(config.file_reload_debounce),
+                )?;
+                for file in &files {
+                    watcher.watch(file)?;
+                }
+                Ok(watcher)
+            };
+            if files.is_empty() {
+                None
+            } else {
+                watch()
+                    .map_err(|e| notifications.error(format!("live reload disabled: {e:#}")))
+                    .ok()
+            }
+        };
         Self {
+            #[cfg(not(target_arch = "wasm32"))]
+            file_watcher,
+            image_path: path,
+            options_args,
+            user_presets: user_presets::list(),
+            preset_name: String::new(),
+            tune: TuneState::default(),
+            render: RenderState::default(),
             settings_file_selection: default(),
+            notifications,
             egui: EguiGeng::new(geng),
             geng: geng.clone(),
             framebuffer_size: vec2::splat(1.0),
             shaders,
             white_texture: ugli::Texture::new_with(geng.ugli(), vec2::splat(1), |_| Rgba::WHITE),
-            sprite: image
-                .as_ref()
-                .map(|image| Sprite::new(geng, image, &sprite_options)),
+            axes: [
+                vec3(1.0, 0.0, 0.0),
+                vec3(0.0, 1.0, 0.0),
+                vec3(0.0, 0.0, 1.0),
+            ]
+            .map(|axis| {
+                ugli::VertexBuffer::new_static(
+                    geng.ugli(),
+                    vec![line_vertex(vec3::ZERO), line_vertex(axis)],
+                )
+            }),
+            generator: generator::Generator::spawn(generator::generate),
+            generation_failed: false,
+            tuner: generator::Worker::spawn(generator::tune),
+            sprite: None,
+            inspection: None,
             sprite_options,
+            history: history::History::new(sprite_options),
+            export_options: default(),
+            export_format: glb::Format::Glb,
             image,
+            image_file,
             camera: Camera {
+                target: vec3::ZERO,
+                orthographic: false,
                 fov: Angle::from_degrees(config.camera.fov),
                 rotation: Angle::from_degrees(config.camera.rotation),
                 attack_angle: Angle::from_degrees(config.camera.attack_angle),
                 distance: config.camera.distance,
             },
             drag: None,
+            touches: HashMap::new(),
             viewer_options: ViewerOptions::new(&config),
             config,
             should_quit: false,
-            should_reload: false,
+            // Generate the initial sprite on the first frame
+            should_reload: true,
             file_selection: default(),
         }
     }
 
-    fn start_drag(&mut self, pos: vec2<f64>) {
+    fn start_drag(&mut self, pos: vec2<f64>, kind: DragKind) {
         if self.egui.get_context().is_pointer_over_area() {
             return;
         }
-        self.drag = Some(pos);
+        self.drag = Some(Drag { kind, last: pos });
     }
 
     fn cursor_move(&mut self, pos: vec2<f64>) {
-        if let Some(prev) = self.drag {
-            self.drag = Some(pos);
-            let delta = pos - prev;
-            self.camera.rotation += Angle::from_degrees(delta.x as f32 * self.config.sensitivity);
-            self.camera.attack_angle -=
-                Angle::from_degrees(delta.y as f32 * self.config.sensitivity);
-            self.camera.attack_angle = self
-                .camera
-                .attack_angle
-                .clamp_range(Angle::from_degrees(-180.0)..=Angle::ZERO);
+        let Some(drag) = &mut self.drag else {
+            return;
+        };
+        let delta = (pos - drag.last).map(|x| x as f32);
+        drag.last = pos;
+        match drag.kind {
+            DragKind::Rotate => {
+                self.camera.rotation += Angle::from_degrees(delta.x * self.config.sensitivity);
+                self.camera.attack_angle -= Angle::from_degrees(delta.y * self.config.sensitivity);
+                self.camera.attack_angle = self
+                    .camera
+                    .attack_angle
+                    .clamp_range(Angle::from_degrees(-90.0)..=Angle::from_degrees(90.0));
+            }
+            DragKind::Pan => {
+                self.camera.pan(delta, self.framebuffer_size);
+            }
         }
     }
 
@@ -196,9 +811,83 @@ impl Viewer {
         self.drag = None;
     }
 
+    fn zoom(&mut self, delta: f64) {
+        if self.egui.get_context().is_pointer_over_area() {
+            return;
+        }
+        self.scale_distance((-delta as f32 * self.config.zoom_sensitivity).exp());
+    }
+
+    fn scale_distance(&mut self, factor: f32) {
+        self.camera.distance *= factor;
+        self.camera.distance = self
+            .camera
+            .distance
+            .clamp(Camera::NEAR * 10.0, Camera::FAR / 2.0);
+    }
+
+    fn set_view(&mut self, preset: ViewPreset) {
+        let (rotation, attack_angle) = preset.angles();
+        self.camera.rotation = Angle::from_degrees(rotation);
+        self.camera.attack_angle = Angle::from_degrees(attack_angle);
+    }
+
+    /// Point the camera at the whole mesh
+    fn frame_object(&mut self) {
+        if let Some(bounding_box) = self
+            .sprite
+            .as_ref()
+            .and_then(|sprite| sprite.mesh.stats.bounding_box)
+        {
+            self.camera.frame(bounding_box);
+        }
+    }
+
+    /// Record the options once they are no longer being dragged
+    fn record_history(&mut self) {
+        if !self.egui.get_context().is_using_pointer() {
+            self.history.record(&self.sprite_options);
+        }
+    }
+
+    fn undo(&mut self) {
+        // Unfinished edits become a step first, so they can be redone
+        self.history.record(&self.sprite_options);
+        if let Some(options) = self.history.undo() {
+            self.sprite_options = options;
+            self.should_reload = true;
+        }
+    }
+
+    fn redo(&mut self) {
+        self.history.record(&self.sprite_options);
+        if let Some(options) = self.history.redo() {
+            self.sprite_options = options;
+            self.should_reload = true;
+        }
+    }
+
     fn ui(&mut self) {
+        let mut undo = false;
+        let mut redo = false;
         egui::Window::new("SpriteShape").show(self.egui.get_context(), |ui| {
             ui.heading("sprite options");
+            ui.horizontal(|ui| {
+                undo = ui
+                    .add_enabled(self.history.can_undo(), egui::Button::new("Undo"))
+                    .on_hover_text("Ctrl+Z")
+                    .clicked();
+                redo = ui
+                    .add_enabled(self.history.can_redo(), egui::Button::new("Redo"))
+                    .on_hover_text("Ctrl+Shift+Z")
+                    .clicked();
+            });
+            if self.generator.is_busy() {
+                ui.horizontal(|ui| {
+                    ui.spinner();
+                    ui.label("generating...");
+                });
+            }
             if ui.button("Select image").clicked() {
                 let selection = self.file_selection.clone();
                 file_dialog::select(move |selected| {
@@ -207,28 +896,87 @@ impl Viewer {
             }
             if ui.button("Export GLTF").clicked() {
                 if let Some(sprite) = &self.sprite {
-                    let _ = file_dialog::save(
-                        "sprite-shape.glb",
-                        &glb::save(self.geng.ugli(), &sprite.shape),
-                    );
+                    let name = self.export_name();
+                    match glb::export(
+                        &sprite.mesh,
+                        &name,
+                        &self.export_options,
+                        self.export_format,
+                    ) {
+                        Ok(files) => {
+                            for file in files {
+                                if let Err(e) = file_dialog::save(&file.name, &file.data) {
+                                    self.notifications
+                                        .error(format!("failed to save {:?}: {e}", file.name));
+                                }
+                            }
+                        }
+                        Err(e) => self.notifications.error(format!("{e:#}")),
+                    }
                 }
             }
-            if ui.button("Load settings.json").clicked() {
+            if ui.button("Load settings (.json / .toml / .glb)").clicked() {
                 let selection = self.settings_file_selection.clone();
                 file_dialog::select(move |selected| {
                     selection.replace(Some(selected));
                 });
             }
-            if ui.button("Export settings.json").clicked() {
-                if let Some(_sprite) = &self.sprite {
-                    let _ = file_dialog::save(
-                        "sprite-shape.json",
-                        serde_json::to_string_pretty(&self.sprite_options)
-                            .unwrap()
-                            .as_bytes(),
-                    );
+            for format in [settings::Format::Json, settings::Format::Toml] {
+                if ui
+                    .button(format!("Export settings.{}", format.extension()))
+                    .clicked()
+                {
+                    let name = format!("sprite-shape.{}", format.extension());
+                    let data = settings::to_string(&self.sprite_options, format);
+                    if let Err(e) = file_dialog::save(&name, data.as_bytes()) {
+                        self.notifications
+                            .error(format!("failed to save {name:?}: {e}"));
+                    }
                 }
             }
+            egui::ComboBox::from_label("preset")
+                .selected_text("apply preset")
+                .show_ui(ui, |ui| {
+                    for preset in presets::builtin() {
+                        if ui
+                            .selectable_label(false, preset.name)
+                            .on_hover_text(preset.description)
+                            .clicked()
+                        {
+                            self.sprite_options = preset.options;
+                            self.should_reload = true;
+                        }
+                    }
+                    for name in &self.user_presets {
+                        if ui.selectable_label(false, name).clicked() {
+                            match user_presets::find(name) {
+                                Ok(options) => {
+                                    self.sprite_options = options;
+                                    self.should_reload = true;
+                                }
+                                Err(e) => {
+                                    self.notifications.error(format!("{e:#}"));
+                                }
+                            }
+                        }
+                    }
+                });
+            if user_presets::dir().is_some() {
+                ui.horizontal(|ui| {
+                    ui.text_edit_singleline(&mut self.preset_name);
+                    if ui.button("Save preset").clicked() {
+                        match user_presets::save(&self.preset_name, &self.sprite_options) {
+                            Ok(path) => {
+                                self.notifications.info(format!("saved preset to {path:?}"));
+                                self.user_presets = user_presets::list();
+                            }
+                            Err(e) => {
+                                self.notifications.error(format!("{e:#}"));
+                            }
+                        }
+                    }
+                });
+            }
             if ui
                 .add(egui::Checkbox::new(
                     &mut self.sprite_options.front_face,
@@ -249,7 +997,7 @@ impl Viewer {
             }
             if ui
                 .add(egui::Slider::new(&mut self.sprite_options.iso, 0.0..=1.0).text("iso"))
-                .drag_released()
+                .changed()
             {
                 self.should_reload = true;
             }
@@ -258,7 +1006,7 @@ impl Viewer {
                     egui::Slider::new(&mut self.sprite_options.blur_sigma, 0.0..=50.0)
                         .text("blur_sigma"),
                 )
-                .drag_released()
+                .changed()
             {
                 self.should_reload = true;
             }
@@ -266,7 +1014,7 @@ impl Viewer {
                 .add(
                     egui::Slider::new(&mut self.sprite_options.cell_size, 1..=50).text("cell_size"),
                 )
-                .drag_released()
+                .changed()
             {
                 self.should_reload = true;
             }
@@ -275,78 +1023,638 @@ impl Viewer {
                     egui::Slider::new(&mut self.sprite_options.thickness, 0.0..=0.1)
                         .text("thickness"),
                 )
-                .drag_released()
+                .changed()
             {
                 self.should_reload = true;
             }
 
+            if let Some(sprite) = &self.sprite {
+                ui.collapsing("stats", |ui| {
+                    let stats = &sprite.mesh.stats;
+                    egui::Grid::new("stats").show(ui, |ui| {
+                        ui.label("");
+                        ui.label("vertices");
+                        ui.label("triangles");
+                        ui.end_row();
+                        for (name, part) in [
+                            ("front", &stats.front),
+                            ("back", &stats.back),
+                            ("side", &stats.side),
+                        ] {
+                            ui.label(name);
+                            ui.label(part.vertices.to_string());
+                            ui.label(part.triangles.to_string());
+                            ui.end_row();
+                        }
+                        ui.label("total");
+                        ui.label(stats.vertices().to_string());
+                        ui.label(stats.triangles().to_string());
+                        ui.end_row();
+                    });
+                    ui.label(format!("islands: {}, holes: {}", stats.islands, stats.holes));
+                    if let Some([min, max]) = stats.bounding_box {
+                        let size = max - min;
+                        ui.label(format!(
+                            "bounding box: {:.3} x {:.3} x {:.3}",
+                            size.x, size.y, size.z,
+                        ));
+                    }
+                    ui.label(format!(
+                        "texture: {}x{}",
+                        stats.texture_size.x, stats.texture_size.y,
+                    ));
+                    let timings = &stats.timings;
+                    ui.label(format!(
+                        "generation: {:.1}ms (blur {:.1}, marching {:.1}, mesh {:.1}, texture {:.1})",
+                        timings.total() * 1000.0,
+                        timings.blur * 1000.0,
+                        timings.marching * 1000.0,
+                        timings.mesh * 1000.0,
+                        timings.texture * 1000.0,
+                    ));
+                });
+                ui.collapsing("silhouette fidelity", |ui| {
+                    let fidelity = &sprite.fidelity;
+                    ui.label(format!("IoU: {:.4}", fidelity.iou));
+                    ui.label(format!("Hausdorff distance: {:.1}px", fidelity.hausdorff));
+                    ui.label(format!("lost area: {}px", fidelity.lost_area));
+                    ui.label(format!("added area: {}px", fidelity.added_area));
+                });
+            }
+
+            ui.collapsing("auto tune", |ui| {
+                ui.radio_value(&mut self.tune.by_error, false, "triangle budget");
+                ui.radio_value(&mut self.tune.by_error, true, "max silhouette error");
+                if self.tune.by_error {
+                    ui.add(
+                        egui::Slider::new(&mut self.tune.max_error, 0.0..=0.2)
+                            .text("max error (1 - IoU)"),
+                    );
+                } else {
+                    ui.add(
+                        egui::Slider::new(&mut self.tune.triangles, 100..=100000)
+                            .logarithmic(true)
+                            .text("max triangles"),
+                    );
+                }
+                if self.tuner.is_busy() {
+                    ui.horizontal(|ui| {
+                        ui.spinner();
+                        ui.label("tuning...");
+                        if ui.button("Cancel").clicked() {
+                            self.tuner.cancel();
+                        }
+                    });
+                } else if ui
+                    .add_enabled(self.image.is_some(), egui::Button::new("Tune"))
+                    .clicked()
+                {
+                    if let Some(image) = &self.image {
+                        let target = if self.tune.by_error {
+                            tune::Target::MaxError(self.tune.max_error)
+                        } else {
+                            tune::Target::Triangles(self.tune.triangles)
+                        };
+                        self.tuner.request(generator::TuneRequest {
+                            image: image.clone(),
+                            options: self.sprite_options,
+                            target,
+                        });
+                    }
+                }
+                if let Some(tuned) = &self.tune.result {
+                    ui.label(format!(
+                        "{} triangles, silhouette error {:.4}{}",
+                        tuned.triangles,
+                        tuned.error,
+                        if tuned.reached {
+                            ""
+                        } else {
+                            " (target not reached)"
+                        },
+                    ));
+                }
+            });
+
+            ui.collapsing("export options", |ui| {
+                egui::ComboBox::from_label("format")
+                    .selected_text(match self.export_format {
+                        glb::Format::Glb => "GLB",
+                        glb::Format::Gltf | glb::Format::GltfEmbedded => "glTF (embedded)",
+                    })
+                    .show_ui(ui, |ui| {
+                        // No separate .bin and .png here: every file gets its own save dialog,
+                        // and renaming one would break the references in the .gltf
+                        ui.selectable_value(&mut self.export_format, glb::Format::Glb, "GLB");
+                        ui.selectable_value(
+                            &mut self.export_format,
+                            glb::Format::GltfEmbedded,
+                            "glTF (embedded)",
+                        );
+                    });
+                let options = &mut self.export_options;
+                egui::ComboBox::from_label("texture filter")
+                    .selected_text(format!("{:?}", options.filter))
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(
+                            &mut options.filter,
+                            glb::TextureFilter::Nearest,
+                            "Nearest",
+                        );
+                        ui.selectable_value(
+                            &mut options.filter,
+                            glb::TextureFilter::Linear,
+                            "Linear",
+                        );
+                    });
+                ui.checkbox(&mut options.clamp, "clamp texture");
+                egui::ComboBox::from_label("alpha mode")
+                    .selected_text(format!("{:?}", options.alpha_mode))
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(
+                            &mut options.alpha_mode,
+                            glb::AlphaMode::Opaque,
+                            "Opaque",
+                        );
+                        ui.selectable_value(&mut options.alpha_mode, glb::AlphaMode::Mask, "Mask");
+                        ui.selectable_value(
+                            &mut options.alpha_mode,
+                            glb::AlphaMode::Blend,
+                            "Blend",
+                        );
+                    });
+                if options.alpha_mode == glb::AlphaMode::Mask {
+                    ui.add(
+                        egui::Slider::new(&mut options.alpha_cutoff, 0.0..=1.0)
+                            .text("alpha cutoff"),
+                    );
+                }
+                ui.checkbox(&mut options.double_sided, "double sided");
+            });
+
+            ui.collapsing("render", |ui| {
+                let render = &mut self.render;
+                ui.checkbox(&mut render.transparent, "transparent background");
+                if ui.button("Screenshot").clicked() {
+                    render.pending = Some(RenderRequest::Screenshot);
+                }
+                ui.add(egui::Slider::new(&mut render.frames, 4..=120).text("frames"));
+                ui.add(egui::Slider::new(&mut render.fps, 1.0..=60.0).text("fps"));
+                egui::ComboBox::from_label("turntable format")
+                    .selected_text(render.format.name())
+                    .show_ui(ui, |ui| {
+                        for format in [
+                            animation::Format::PngSequence,
+                            animation::Format::Gif,
+                            animation::Format::Apng,
+                        ] {
+                            ui.selectable_value(&mut render.format, format, format.name());
+                        }
+                    });
+                if ui.button("Render turntable").clicked() {
+                    render.pending = Some(RenderRequest::Turntable);
+                }
+            });
+
             ui.heading("viewer options");
             ui.checkbox(&mut self.viewer_options.wireframe, "wireframe");
             ui.checkbox(&mut self.viewer_options.culling, "culling");
+            egui::ComboBox::from_label("render mode")
+                .selected_text(self.viewer_options.render_mode.name())
+                .show_ui(ui, |ui| {
+                    for mode in RenderMode::ALL {
+                        ui.selectable_value(
+                            &mut self.viewer_options.render_mode,
+                            mode,
+                            mode.name(),
+                        );
+                    }
+                });
+            ui.checkbox(&mut self.viewer_options.normal_lines, "normal lines");
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut self.viewer_options.ground_grid, "ground grid");
+                ui.checkbox(&mut self.viewer_options.axes, "axes");
+                ui.checkbox(&mut self.viewer_options.human_reference, "1.8m human");
+            });
+            let light = &mut self.viewer_options.light;
+            ui.checkbox(&mut light.enabled, "lighting");
+            if light.enabled {
+                ui.add(egui::Slider::new(&mut light.azimuth, -180.0..=180.0).text("light azimuth"));
+                ui.add(
+                    egui::Slider::new(&mut light.elevation, -90.0..=90.0).text("light elevation"),
+                );
+                ui.add(egui::Slider::new(&mut light.ambient, 0.0..=1.0).text("ambient"));
+                ui.checkbox(&mut self.viewer_options.rim_light, "rim light");
+                if self.viewer_options.rim_light {
+                    ui.add(egui::Slider::new(&mut light.rim, 0.0..=1.0).text("rim strength"));
+                }
+            }
+            ui.horizontal(|ui| {
+                for preset in ViewPreset::ALL {
+                    if ui.button(preset.name()).clicked() {
+                        self.set_view(preset);
+                    }
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut self.camera.orthographic, "orthographic");
+                if ui.button("Frame object").clicked() {
+                    self.frame_object();
+                }
+            });
+            ui.checkbox(&mut self.viewer_options.inspect, "inspect 2D");
+            if self.viewer_options.inspect {
+                let overlays = &mut self.viewer_options.overlays;
+                ui.checkbox(&mut overlays.field, "blurred field");
+                ui.checkbox(&mut overlays.contour, "iso contour");
+                ui.checkbox(&mut overlays.grid, "marching grid");
+                ui.checkbox(&mut overlays.texture, "dilated texture");
+            }
 
             ui.label("background color");
             let mut color = self.viewer_options.background_color.to_vec4();
             ui.color_edit_button_rgb((&mut color[..3]).try_into().unwrap());
             self.viewer_options.background_color = Rgba::from_vec4(color);
         });
+        if undo {
+            self.undo();
+        }
+        if redo {
+            self.redo();
+        }
     }
-    fn update(&mut self, _delta_time: time::Duration) {
+    fn update(&mut self, delta_time: time::Duration) {
+        self.notifications.update(delta_time.as_secs_f64());
         self.egui.begin_frame();
         self.ui();
+        self.notifications.ui(self.egui.get_context());
         self.egui.end_frame();
+        self.record_history();
+        if self.viewer_options.inspect
+            && self.inspection.is_none()
+            && self.image.is_some()
+            && !self.generator.is_busy()
+            && !self.generation_failed
+        {
+            self.should_reload = true;
+        }
     }
     fn draw(&mut self, framebuffer: &mut ugli::Framebuffer) {
         self.framebuffer_size = framebuffer.size().map(|x| x as f32);
-        ugli::clear(
-            framebuffer,
-            Some(self.viewer_options.background_color),
-            Some(1.0),
-            None,
+        self.draw_scene(framebuffer, self.viewer_options.background_color);
+
+        if self.viewer_options.axes && !self.viewer_options.inspect {
+            self.draw_axes(framebuffer);
+        }
+
+        self.egui.draw(framebuffer);
+    }
+
+    /// Use the options from a settings file or an exported glTF
+    fn open_settings(&mut self, name: &str, data: &[u8]) {
+        let name = name.to_lowercase();
+        let options = if is_gltf_file(&name) {
+            glb::load_provenance(data).and_then(|provenance| {
+                provenance.options.validate()?;
+                Ok(provenance.options)
+            })
+        } else {
+            settings::parse(data, settings::Format::from_file_name(&name))
+        };
+        match options {
+            Ok(options) => {
+                self.sprite_options = options;
+                self.should_reload = true;
+            }
+            Err(e) => {
+                self.notifications
+                    .error(format!("failed to load settings from {name:?}: {e:#}"));
+            }
+        }
+    }
+
+    fn open_image(&mut self, name: &str, data: &[u8]) {
+        match geng::image::load_from_memory(data) {
+            Ok(image) => {
+                self.image = Some(Arc::new(image.into()));
+                self.image_file = Some(name.to_owned());
+                self.image_path = None;
+                self.should_reload = true;
+            }
+            Err(e) => {
+                self.notifications
+                    .error(format!("failed to load image {name:?}: {e}"));
+            }
+        }
+    }
+
+    /// File name stem for exported files
+    fn export_name(&self) -> String {
+        self.image_file
+            .as_ref()
+            .and_then(|file| std::path::Path::new(file).file_stem())
+            .map_or("sprite-shape".into(), |stem| {
+                stem.to_string_lossy().into_owned()
+            })
+    }
+
+    /// Draw the scene at the current window size into an image
+    fn render_image(&self) -> geng::image::RgbaImage {
+        let ugli = self.geng.ugli();
+        let size = self.framebuffer_size.map(|x| x as usize);
+        let mut texture = ugli::Texture::new_uninitialized(ugli, size);
+        let mut depth = ugli::Renderbuffer::<ugli::DepthComponent>::new(ugli, size);
+        let mut framebuffer = ugli::Framebuffer::new(
+            ugli,
+            ugli::ColorAttachment::Texture(&mut texture),
+            ugli::DepthAttachment::Renderbuffer(&mut depth),
         );
-        if let Some(sprite) = &self.sprite {
+        let mut background_color = self.viewer_options.background_color;
+        if self.render.transparent {
+            background_color.a = 0.0;
+        }
+        self.draw_scene(&mut framebuffer, background_color);
+        let pixels = framebuffer.read_color();
+        let vec2(width, height) = size.map(|x| x as u32);
+        // Framebuffer rows go bottom to top
+        geng::image::RgbaImage::from_fn(width, height, |x, y| {
+            let color = pixels.get(x as usize, (height - 1 - y) as usize);
+            geng::image::Rgba([color.r, color.g, color.b, color.a])
+        })
+    }
+
+    /// Render one full rotation of the camera around the object
+    fn render_turntable(&mut self) -> Vec<geng::image::RgbaImage> {
+        let rotation = self.camera.rotation;
+        let frames = (0..self.render.frames)
+            .map(|frame| {
+                self.camera.rotation = rotation
+                    + Angle::from_degrees(360.0 * frame as f32 / self.render.frames as f32);
+                self.render_image()
+            })
+            .collect();
+        self.camera.rotation = rotation;
+        frames
+    }
+
+    fn process_render_request(&mut self) {
+        let Some(request) = self.render.pending.take() else {
+            return;
+        };
+        let Some(provenance) = self
+            .sprite
+            .as_ref()
+            .map(|sprite| sprite.mesh.provenance.clone())
+        else {
+            return;
+        };
+        let name = self.export_name();
+        let file = match request {
+            RenderRequest::Screenshot => animation::encode_png(&self.render_image(), &provenance)
+                .map(|data| glb::File {
+                    name: format!("{name}.png"),
+                    data,
+                }),
+            RenderRequest::Turntable => {
+                let frames = self.render_turntable();
+                animation::encode(
+                    &frames,
+                    &format!("{name}_turntable"),
+                    self.render.format,
+                    self.render.fps,
+                    &provenance,
+                )
+            }
+        };
+        match file {
+            Ok(file) => {
+                if let Err(e) = file_dialog::save(&file.name, &file.data) {
+                    self.notifications
+                        .error(format!("failed to save {:?}: {e}", file.name));
+                }
+            }
+            Err(e) => self.notifications.error(format!("{e:#}")),
+        }
+    }
+
+    /// Everything except the UI and the axes gizmo
+    fn draw_scene(&self, framebuffer: &mut ugli::Framebuffer, background_color: Rgba<f32>) {
+        ugli::clear(framebuffer, Some(background_color), Some(1.0), None);
+        let Some(shaders) = &self.shaders else {
+            return;
+        };
+        if self.viewer_options.inspect {
+            if let Some(inspection) = &self.inspection {
+                self.draw_inspection(framebuffer, inspection);
+            }
+        } else if let Some(sprite) = &self.sprite {
+            self.draw_helpers(framebuffer, &sprite.helpers);
             if self.viewer_options.wireframe {
-                ugli::draw(
+                self.draw_lines(
                     framebuffer,
-                    &self.shaders.wireframe,
-                    ugli::DrawMode::Lines { line_width: 1.0 },
                     &sprite.wireframe_geometry,
+                    self.config.wireframe_color,
+                );
+            }
+            if self.viewer_options.normal_lines {
+                self.draw_lines(framebuffer, &sprite.normal_lines, self.config.normal_color);
+            }
+            let draw_parameters = ugli::DrawParameters {
+                depth_func: Some(ugli::DepthFunc::Less),
+                cull_face: self.viewer_options.culling.then_some(ugli::CullFace::Back),
+                ..default()
+            };
+            let light = &self.viewer_options.light;
+            match self.viewer_options.render_mode {
+                RenderMode::Textured => ugli::draw(
+                    framebuffer,
+                    &shaders.program,
+                    ugli::DrawMode::Triangles,
+                    &sprite.shape.mesh,
                     (
                         ugli::uniforms! {
-                            u_texture: &self.white_texture,
-                            u_color: self.config.wireframe_color,
+                            u_texture: &sprite.shape.texture,
+                            u_lighting: if light.enabled { 1.0 } else { 0.0 },
+                            u_light_direction: light.direction(),
+                            u_light_color: light.color,
+                            u_ambient: light.ambient,
+                            u_rim: if self.viewer_options.rim_light { light.rim } else { 0.0 },
                         },
                         self.camera.uniforms(self.framebuffer_size),
                     ),
-                    ugli::DrawParameters {
-                        depth_func: Some(ugli::DepthFunc::LessOrEqual),
-                        ..default()
-                    },
-                );
+                    draw_parameters,
+                ),
+                mode => ugli::draw(
+                    framebuffer,
+                    &shaders.debug,
+                    ugli::DrawMode::Triangles,
+                    &sprite.shape.mesh,
+                    (
+                        ugli::uniforms! {
+                            u_texture: &sprite.shape.texture,
+                            u_mode: mode.shader_mode(),
+                            u_checker_size: self.config.checker_size,
+                        },
+                        self.camera.uniforms(self.framebuffer_size),
+                    ),
+                    draw_parameters,
+                ),
             }
+        }
+    }
+
+    fn draw_lines(
+        &self,
+        framebuffer: &mut ugli::Framebuffer,
+        lines: &ugli::VertexBuffer<Vertex>,
+        color: Rgba<f32>,
+    ) {
+        let Some(shaders) = &self.shaders else {
+            return;
+        };
+        ugli::draw(
+            framebuffer,
+            &shaders.wireframe,
+            ugli::DrawMode::Lines { line_width: 1.0 },
+            lines,
+            (
+                ugli::uniforms! {
+                    u_texture: &self.white_texture,
+                    u_color: color,
+                },
+                self.camera.uniforms(self.framebuffer_size),
+            ),
+            ugli::DrawParameters {
+                depth_func: Some(ugli::DepthFunc::LessOrEqual),
+                ..default()
+            },
+        );
+    }
+
+    fn draw_helpers(&self, framebuffer: &mut ugli::Framebuffer, helpers: &SceneHelpers) {
+        let config = &self.config.helpers;
+        if self.viewer_options.ground_grid {
+            self.draw_lines(framebuffer, &helpers.grid_minor, config.grid_color);
+            self.draw_lines(framebuffer, &helpers.grid_major, config.grid_major_color);
+        }
+        if self.viewer_options.human_reference {
+            self.draw_lines(framebuffer, &helpers.human, config.human_color);
+        }
+    }
+
+    /// Axes rotated like the camera, drawn in the bottom left corner
+    fn draw_axes(&self, framebuffer: &mut ugli::Framebuffer) {
+        let Some(shaders) = &self.shaders else {
+            return;
+        };
+        let size = self.config.helpers.axes_size;
+        let pixel = vec2(2.0, 2.0) / self.framebuffer_size;
+        let projection_matrix = mat4::translate(vec3(
+            -1.0 + pixel.x * size * 1.5,
+            -1.0 + pixel.y * size * 1.5,
+            0.0,
+        )) * mat4::scale(vec3(pixel.x * size, pixel.y * size, 0.01));
+        let colors = [
+            Rgba::new(1.0, 0.2, 0.2, 1.0),
+            Rgba::new(0.2, 1.0, 0.2, 1.0),
+            Rgba::new(0.3, 0.5, 1.0, 1.0),
+        ];
+        for (axis, color) in self.axes.iter().zip(colors) {
             ugli::draw(
                 framebuffer,
-                &self.shaders.program,
-                ugli::DrawMode::Triangles,
-                &sprite.shape.mesh,
-                (
-                    ugli::uniforms! {
-                        u_texture: &sprite.shape.texture,
-                    },
-                    self.camera.uniforms(self.framebuffer_size),
-                ),
-                ugli::DrawParameters {
-                    depth_func: Some(ugli::DepthFunc::Less),
-                    cull_face: self.viewer_options.culling.then_some(ugli::CullFace::Back),
-                    ..default()
+                &shaders.wireframe,
+                ugli::DrawMode::Lines { line_width: 2.0 },
+                axis,
+                ugli::uniforms! {
+                    u_texture: &self.white_texture,
+                    u_color: color,
+                    u_view_matrix: self.camera.rotation_matrix(),
+                    u_projection_matrix: projection_matrix,
+                },
+                ugli::DrawParameters::default(),
+            );
+        }
+    }
+    fn draw_inspection(&self, framebuffer: &mut ugli::Framebuffer, inspection: &InspectionView) {
+        let Some(shaders) = &self.shaders else {
+            return;
+        };
+        let overlays = &self.viewer_options.overlays;
+        let matrix = inspection.matrix(self.framebuffer_size);
+        let draw_parameters = ugli::DrawParameters {
+            blend_mode: Some(ugli::BlendMode::straight_alpha()),
+            ..default()
+        };
+        let mut draw = |mode: ugli::DrawMode,
+                        vertices: &ugli::VertexBuffer<FlatVertex>,
+                        texture: &ugli::Texture,
+                        color: Rgba<f32>| {
+            ugli::draw(
+                framebuffer,
+                &shaders.flat,
+                mode,
+                vertices,
+                ugli::uniforms! {
+                    u_matrix: matrix,
+                    u_texture: texture,
+                    u_color: color,
                 },
+                &draw_parameters,
+            );
+        };
+        let image = if overlays.texture {
+            &inspection.dilated
+        } else {
+            &inspection.source
+        };
+        draw(
+            ugli::DrawMode::TriangleFan,
+            &inspection.quad,
+            image,
+            Rgba::WHITE,
+        );
+        if overlays.field {
+            draw(
+                ugli::DrawMode::TriangleFan,
+                &inspection.quad,
+                &inspection.field,
+                Rgba::new(1.0, 1.0, 1.0, 0.75),
+            );
+        }
+        let lines = ugli::DrawMode::Lines { line_width: 1.0 };
+        if overlays.grid {
+            draw(
+                lines,
+                &inspection.grid,
+                &self.white_texture,
+                self.config.marching_grid_color,
+            );
+        }
+        if overlays.contour {
+            draw(
+                lines,
+                &inspection.contour,
+                &self.white_texture,
+                self.config.contour_color,
             );
         }
+    }
 
-        self.egui.draw(framebuffer);
+    fn ctrl_pressed(&self) -> bool {
+        let window = self.geng.window();
+        window.is_key_pressed(geng::Key::LCtrl) || window.is_key_pressed(geng::Key::RCtrl)
+    }
+
+    fn shift_pressed(&self) -> bool {
+        let window = self.geng.window();
+        window.is_key_pressed(geng::Key::LShift) || window.is_key_pressed(geng::Key::RShift)
     }
+
     fn handle_event(&mut self, event: geng::Event) {
         self.egui.handle_event(event.clone());
         match event {
+            // Keys typed into text fields are not shortcuts
+            geng::Event::KeyPress { .. } if self.egui.get_context().wants_keyboard_input() => {}
             geng::Event::KeyPress { key } => match key {
                 geng::Key::C => {
                     self.viewer_options.culling = !self.viewer_options.culling;
@@ -354,16 +1662,54 @@ impl Viewer {
                 geng::Key::W => {
                     self.viewer_options.wireframe = !self.viewer_options.wireframe;
                 }
+                geng::Key::L => {
+                    self.viewer_options.light.enabled = !self.viewer_options.light.enabled;
+                }
+                geng::Key::G => {
+                    self.viewer_options.ground_grid = !self.viewer_options.ground_grid;
+                }
+                geng::Key::H => {
+                    self.viewer_options.human_reference = !self.viewer_options.human_reference;
+                }
+                geng::Key::N => {
+                    self.viewer_options.normal_lines = !self.viewer_options.normal_lines;
+                }
+                geng::Key::I => {
+                    self.viewer_options.inspect = !self.viewer_options.inspect;
+                }
+                geng::Key::Num1 => self.set_view(ViewPreset::Front),
+                geng::Key::Num2 => self.set_view(ViewPreset::Back),
+                geng::Key::Num3 => self.set_view(ViewPreset::Left),
+                geng::Key::Num4 => self.set_view(ViewPreset::Right),
+                geng::Key::Num5 => self.set_view(ViewPreset::Top),
+                geng::Key::O => {
+                    self.camera.orthographic = !self.camera.orthographic;
+                }
+                geng::Key::F => self.frame_object(),
+                geng::Key::Z if self.ctrl_pressed() => {
+                    if self.shift_pressed() {
+                        self.redo();
+                    } else {
+                        self.undo();
+                    }
+                }
                 geng::Key::Escape => {
                     self.should_quit = true;
                 }
                 _ => {}
             },
-            geng::Event::MousePress { .. } => {
+            geng::Event::MousePress { button } => {
+                let kind = match button {
+                    geng::MouseButton::Left => DragKind::Rotate,
+                    _ => DragKind::Pan,
+                };
                 if let Some(cursor_pos) = self.geng.window().cursor_position() {
-                    self.start_drag(cursor_pos);
+                    self.start_drag(cursor_pos, kind);
                 }
             }
+            geng::Event::Wheel { delta } => {
+                self.zoom(delta);
+            }
             geng::Event::CursorMove {
                 position: cursor_pos,
             } => {
@@ -372,17 +1718,134 @@ impl Viewer {
             geng::Event::MouseRelease { .. } => {
                 self.stop_drag();
             }
+            geng::Event::TouchStart(touch) => {
+                self.touches.insert(touch.id, touch.position);
+                match self.touches.len() {
+                    1 => self.start_drag(touch.position, DragKind::Rotate),
+                    _ => self.stop_drag(),
+                }
+            }
+            geng::Event::TouchMove(touch) => {
+                self.touch_move(touch);
+            }
+            geng::Event::TouchEnd(touch) => {
+                self.touches.remove(&touch.id);
+                self.stop_drag();
+            }
             _ => {}
         }
     }
 
+    /// One finger rotates, two fingers pinch to zoom
+    fn touch_move(&mut self, touch: geng::Touch) {
+        if self.touches.len() == 1 {
+            self.touches.insert(touch.id, touch.position);
+            self.cursor_move(touch.position);
+            return;
+        }
+        let spread = |touches: &HashMap<u64, vec2<f64>>| {
+            let mut positions = touches.values();
+            match (positions.next(), positions.next()) {
+                (Some(&a), Some(&b)) => (a - b).len(),
+                _ => 0.0,
+            }
+        };
+        let before = spread(&self.touches);
+        self.touches.insert(touch.id, touch.position);
+        let after = spread(&self.touches);
+        if before > 0.0 && after > 0.0 {
+            self.scale_distance((before / after) as f32);
+        }
+    }
+
+    /// Apply the result of auto tune once the search is done
+    fn poll_tune(&mut self) {
+        match self.tuner.poll() {
+            Some(Ok(tuned)) => {
+                self.sprite_options = tuned.options;
+                self.should_reload = true;
+                self.tune.result = Some(tuned);
+            }
+            Some(Err(e)) => self.notifications.error(format!("{e:#}")),
+            None => {}
+        }
+    }
+
     async fn maybe_reload(&mut self) {
         if self.should_reload {
             if let Some(image) = &self.image {
-                self.sprite = Some(Sprite::new(&self.geng, image, &self.sprite_options));
+                self.generator.request(generator::Request {
+                    image: image.clone(),
+                    source_file: self.image_file.clone(),
+                    options: self.sprite_options,
+                    inspect: self.viewer_options.inspect,
+                });
+                self.generation_failed = false;
             }
             self.should_reload = false;
         }
+        match self.generator.poll() {
+            Some(Ok(generated)) => {
+                self.inspection = generated.inspection.map(|inspection| {
+                    InspectionView::new(
+                        &self.geng,
+                        &generated.image,
+                        &generated.mesh.texture,
+                        inspection,
+                    )
+                });
+                self.sprite = Some(Sprite::new(
+                    &self.geng,
+                    generated.mesh,
+                    generated.fidelity,
+                    &self.config,
+                ));
+            }
+            // The previous sprite stays so there is still something to look at
+            Some(Err(e)) => {
+                self.notifications.error(format!("{e:#}"));
+                self.generation_failed = true;
+            }
+            None => {}
+        }
+    }
+
+    /// Reload the image or settings given on the command line if they have changed on disk
+    #[cfg(not(target_arch = "wasm32"))]
+    fn reload_changed_files(&mut self) {
+        let Some(watcher) = &mut self.file_watcher else {
+            return;
+        };
+        for path in watcher.poll() {
+            if Some(&path) == self.options_args.settings.as_ref() {
+                match self.options_args.resolve() {
+                    Ok(options) => {
+                        self.notifications.info(format!("reloaded {path:?}"));
+                        self.sprite_options = options;
+                        self.should_reload = true;
+                    }
+                    Err(e) => {
+                        self.notifications.error(format!("{e:#}"));
+                    }
+                }
+            }
+            if Some(&path) == self.image_path.as_ref() {
+                match geng::image::open(&path) {
+                    Ok(image) => {
+                        self.notifications.info(format!("reloaded {path:?}"));
+                        self.image = Some(Arc::new(image.into_rgba8()));
+                        self.image_file = path
+                            .file_name()
+                            .map(|name| name.to_string_lossy().into_owned());
+                        self.should_reload = true;
+                    }
+                    Err(e) => {
+                        self.notifications
+                            .error(format!("failed to reload {path:?}: {e}"));
+                    }
+                }
+            }
+        }
     }
 
     pub async fn run(mut self) {
@@ -391,34 +1854,31 @@ impl Viewer {
         while let Some(event) = geng.window().events().next().await {
             if let geng::Event::Draw = event {
                 self.update(timer.tick());
+                #[cfg(not(target_arch = "wasm32"))]
+                self.reload_changed_files();
                 if let Some(file) = self.settings_file_selection.take() {
-                    if let Ok(mut reader) = file.reader() {
-                        let mut buf = Vec::new();
-                        if reader.read_to_end(&mut buf).await.is_ok() {
-                            self.sprite_options = serde_json::from_slice(&buf).unwrap();
-                            self.should_reload = true;
-                        }
+                    let name = file.name().to_string_lossy().into_owned();
+                    match read_selected_file(file).await {
+                        Ok(data) => self.open_settings(&name, &data),
+                        Err(e) => self
+                            .notifications
+                            .error(format!("failed to read {name:?}: {e}")),
                     }
                 }
                 if let Some(file) = self.file_selection.take() {
-                    if let Ok(mut reader) = file.reader() {
-                        let mut buf = Vec::new();
-                        if reader.read_to_end(&mut buf).await.is_ok() {
-                            match geng::image::load_from_memory(&buf) {
-                                Ok(image) => {
-                                    self.image = Some(image.into());
-                                    self.should_reload = true;
-                                }
-                                Err(e) => {
-                                    log::error!("error: {e}");
-                                }
-                            }
-                        }
+                    let name = file.name().to_string_lossy().into_owned();
+                    match read_selected_file(file).await {
+                        Ok(data) => self.open_image(&name, &data),
+                        Err(e) => self
+                            .notifications
+                            .error(format!("failed to read {name:?}: {e}")),
                     }
                 }
                 geng.window().with_framebuffer(|framebuffer| {
                     self.draw(framebuffer);
                 });
+                self.process_render_request();
+                self.poll_tune();
                 self.maybe_reload().await;
             } else {
                 self.handle_event(event);
@@ -0,0 +1,85 @@
+use super::*;
+
+use file_watcher::{canonical, parent_dir};
+
+#[derive(clap::Args)]
+pub struct WatchArgs {
+    #[clap(flatten)]
+    batch: batch::BatchArgs,
+    /// Milliseconds without further changes to wait for before regenerating
+    #[clap(long, default_value = "300")]
+    debounce: u64,
+}
+
+/// Directory to watch for an input, and whether to include its subdirectories
+///
+/// A single file is watched through its directory so that its sidecars are seen too.
+fn watch_root(input: &str) -> (PathBuf, bool) {
+    let path = std::path::Path::new(input);
+    if path.is_dir() {
+        (path.to_owned(), true)
+    } else if path.is_file() {
+        (parent_dir(path), false)
+    } else {
+        let base = batch::glob_base(input);
+        let base = if base.as_os_str().is_empty() {
+            PathBuf::from(".")
+        } else {
+            base
+        };
+        (base, true)
+    }
+}
+
+/// Regenerate jobs affected by the changed files, or all of them if `changed` is `None`
+fn regenerate(
+    pool: &rayon::ThreadPool,
+    args: &batch::BatchArgs,
+    changed: Option<&HashSet<PathBuf>>,
+) -> anyhow::Result<()> {
+    let options = args.options.resolve()?;
+    let jobs = batch::collect_jobs(&args.inputs, &args.output)?;
+    let is_changed =
+        |path: &std::path::Path| changed.map_or(true, |changed| changed.contains(&canonical(path)));
+    let settings_changed = args
+        .options
+        .settings
+        .as_deref()
+        .map_or(changed.is_none(), is_changed);
+    let jobs: Vec<&batch::Job> = jobs
+        .iter()
+        .filter(|job| {
+            settings_changed
+                || is_changed(&job.input)
+                || job.sidecars().iter().any(|sidecar| is_changed(sidecar))
+        })
+        .collect();
+    if !jobs.is_empty() {
+        batch::run_jobs(pool, &jobs, args, &options);
+    }
+    Ok(())
+}
+
+pub fn run(args: WatchArgs) -> anyhow::Result<()> {
+    let batch = &args.batch;
+    let pool = batch::thread_pool(batch)?;
+    let debounce = std::time::Duration::from_millis(args.debounce);
+
+    let mut watcher = file_watcher::FileWatcher::new(debounce)?;
+    for input in &batch.inputs {
+        let (path, recursive) = watch_root(input);
+        watcher.watch_dir(&path, recursive)?;
+    }
+    if let Some(settings) = &batch.options.settings {
+        watcher.watch_dir(&parent_dir(settings), false)?;
+    }
+
+    let mut changed = None;
+    loop {
+        if let Err(e) = regenerate(&pool, batch, changed.as_ref()) {
+            eprintln!("error: {e:#}");
+        }
+        println!("watching for changes...");
+        changed = Some(watcher.wait()?.into_iter().collect());
+    }
+}